@@ -0,0 +1,50 @@
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error
+    },
+    Gltf {
+        path: PathBuf,
+        source: gltf::Error
+    },
+    MissingAttribute {
+        path: PathBuf,
+        mesh: usize,
+        primitive: usize,
+        attribute: &'static str
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, source } => {
+                write!(f, "failed to read '{}': {}", path.display(), source)
+            },
+            LoadError::Gltf { path, source } => {
+                write!(f, "failed to parse glTF '{}': {}", path.display(), source)
+            },
+            LoadError::MissingAttribute { path, mesh, primitive, attribute } => {
+                write!(
+                    f,
+                    "'{}': mesh {} primitive {} is missing required attribute {}",
+                    path.display(), mesh, primitive, attribute
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Gltf { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
@@ -6,6 +6,8 @@ mod window;
 use window::{Window, Framebuffer};
 mod model;
 use model::{Model, Vertex, load_model};
+mod error;
+use error::LoadError;
 
 fn from_u8_rgb(r: u8, g: u8, b: u8) -> u32 {
     let (r, g, b) = (r as u32, g as u32, b as u32);
@@ -115,7 +117,13 @@ fn main() {
     let mut window = Window::new("3D graphics from scratch! (PART 2)", 512, 512);
     let mut depth_buffer = Framebuffer::new(window.framebuffer().width(), window.framebuffer().height());
 
-    let model = load_model("assets/DamagedHelmet/DamagedHelmet.gltf");
+    let model = match load_model("assets/DamagedHelmet/DamagedHelmet.gltf") {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("Failed to load model: {}", err);
+            std::process::exit(1);
+        }
+    };
 
     let timer = SystemTime::now();
 
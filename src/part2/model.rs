@@ -1,4 +1,6 @@
 use glam::*;
+use crate::LoadError;
+use std::path::Path;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vertex {
@@ -19,12 +21,15 @@ impl Default for Vertex {
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    // Loaded for the next part, which shades with materials.
+    #[allow(dead_code)]
     pub material_idx: usize
 }
 
 #[derive(Clone, Debug)]
 pub struct Model {
     pub meshes: Vec<Mesh>,
+    #[allow(dead_code)]
     pub materials: Vec<Material>
 }
 
@@ -41,9 +46,13 @@ impl Default for Material {
     }
 }
 
-pub fn load_model(file_path: &str) -> Model {
+pub fn load_model<P: AsRef<Path>>(file_path: P) -> Result<Model, LoadError> {
+    let file_path = file_path.as_ref();
     let (document, buffers, _images) = gltf::import(file_path)
-        .expect("Failed to load model.");
+        .map_err(|err| match err {
+            gltf::Error::Io(source) => LoadError::Io { path: file_path.to_path_buf(), source },
+            source => LoadError::Gltf { path: file_path.to_path_buf(), source }
+        })?;
 
     let mut meshes = Vec::new();
     let mut materials = vec![Material::default(); document.materials().len()];
@@ -51,27 +60,29 @@ pub fn load_model(file_path: &str) -> Model {
         materials.push(Material::default());
     }
     
-    if document.nodes().len() > 0 {
+    if let Some(node) = document.nodes().next() {
         process_node(
-            document.nodes().next().as_ref().unwrap(),
+            &node,
             &buffers,
             &mut meshes,
-            &mut materials
-        );
+            &mut materials,
+            file_path
+        )?;
     }
 
-    Model {
+    Ok(Model {
         meshes,
         materials
-    }
+    })
 }
 
 fn process_node(
     node: &gltf::Node,
     buffers: &[gltf::buffer::Data],
     meshes: &mut Vec<Mesh>,
-    materials: &mut [Material]
-) {
+    materials: &mut [Material],
+    file_path: &Path
+) -> Result<(), LoadError> {
     if let Some(mesh) = node.mesh() {
         for primitive in mesh.primitives() {
             if primitive.mode() == gltf::mesh::Mode::Triangles {
@@ -79,10 +90,17 @@ fn process_node(
                     |buffer| Some(&buffers[buffer.index()])
                 );
 
+                let missing_attribute = |attribute| LoadError::MissingAttribute {
+                    path: file_path.to_path_buf(),
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                    attribute
+                };
+
                 let positions = {
                     let iter = reader
                         .read_positions()
-                        .expect("Failed to process mesh node. (Vertices must have positions)");
+                        .ok_or_else(|| missing_attribute("POSITION"))?;
 
                     iter.map(|arr| -> Vec3 { Vec3::from(arr) }).collect::<Vec<_>>()
                 };
@@ -106,7 +124,7 @@ fn process_node(
                     .read_indices()
                     .map(|read_indices| {
                         read_indices.into_u32().collect::<Vec<_>>()
-                    }).expect("Failed to process mesh node. (Indices are required)");
+                    }).ok_or_else(|| missing_attribute("indices"))?;
                 
                 let prim_material = primitive.material();
                 let pbr = prim_material.pbr_metallic_roughness();
@@ -123,4 +141,6 @@ fn process_node(
             }
         }
     }
-}
\ No newline at end of file
+
+    Ok(())
+}
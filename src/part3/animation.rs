@@ -0,0 +1,270 @@
+use glam::*;
+use std::ops::{Add, Mul};
+use crate::Model;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    // Rotations are spherically interpolated.
+    Linear,
+    // Hermite spline, every keyframe stores an in tangent, the value and an out tangent.
+    CubicSpline
+}
+
+#[derive(Clone, Debug)]
+pub enum ChannelValues {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    // Morph target weights of every keyframe, one after the other.
+    Weights(Vec<f32>)
+}
+
+// Keyframes of one property of a node.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub node: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: ChannelValues
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    // Time of the last keyframe of any channel, in seconds.
+    pub duration: f32
+}
+
+// The animatable properties of a node.
+#[derive(Clone, Debug)]
+pub struct NodePose {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    pub weights: Vec<f32>
+}
+
+impl NodePose {
+    fn blend(&self, other: &NodePose, t: f32) -> NodePose {
+        NodePose {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+            weights: (0..self.weights.len().max(other.weights.len()))
+                .map(|i| {
+                    let a = self.weights.get(i).copied().unwrap_or(0.0);
+                    let b = other.weights.get(i).copied().unwrap_or(0.0);
+                    a + (b - a) * t
+                })
+                .collect()
+        }
+    }
+}
+
+trait Keyframe: Copy + Add<Output = Self> + Mul<f32, Output = Self> {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+
+    fn normalized(self) -> Self {
+        self
+    }
+}
+
+impl Keyframe for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Keyframe for Vec3 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+impl Keyframe for Quat {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+
+    fn normalized(self) -> Self {
+        self.normalize()
+    }
+}
+
+// Value of the keyframes at `time`, holding the first and last values outside of their range.
+fn sample<T: Keyframe>(times: &[f32], values: &[T], interpolation: Interpolation, time: f32) -> T {
+    let stride = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+    let value = |i: usize| values[i * stride + stride / 2];
+
+    let last = times.len() - 1;
+    if time <= times[0] {
+        return value(0);
+    }
+    if time >= times[last] {
+        return value(last);
+    }
+
+    let i = times.partition_point(|keyframe_time| *keyframe_time <= time) - 1;
+    let delta = times[i + 1] - times[i];
+    let t = (time - times[i]) / delta;
+
+    match interpolation {
+        Interpolation::Step => value(i),
+        Interpolation::Linear => value(i).interpolate(value(i + 1), t),
+        Interpolation::CubicSpline => {
+            let out_tangent = values[i * 3 + 2] * delta;
+            let in_tangent = values[(i + 1) * 3] * delta;
+            let t2 = t * t;
+            let t3 = t2 * t;
+            (value(i) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + out_tangent * (t3 - 2.0 * t2 + t)
+                + value(i + 1) * (-2.0 * t3 + 3.0 * t2)
+                + in_tangent * (t3 - t2)).normalized()
+        }
+    }
+}
+
+impl AnimationClip {
+    // Overwrites the properties of `pose` animated by this clip with their values at `time`.
+    pub fn sample(&self, time: f32, pose: &mut [NodePose]) {
+        for channel in &self.channels {
+            if channel.times.is_empty() {
+                continue;
+            }
+            let node = &mut pose[channel.node];
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    node.translation = sample(&channel.times, values, channel.interpolation, time);
+                },
+                ChannelValues::Rotation(values) => {
+                    node.rotation = sample(&channel.times, values, channel.interpolation, time);
+                },
+                ChannelValues::Scale(values) => {
+                    node.scale = sample(&channel.times, values, channel.interpolation, time);
+                },
+                ChannelValues::Weights(values) => {
+                    let target_count = values.len() / channel.times.len() / if channel.interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+                    node.weights = (0..target_count)
+                        .map(|target| {
+                            let target_values: Vec<f32> = values.iter().skip(target).step_by(target_count).copied().collect();
+                            sample(&channel.times, &target_values, channel.interpolation, time)
+                        })
+                        .collect();
+                }
+            }
+        }
+    }
+}
+
+// A clip that is being faded into.
+struct CrossFade {
+    clip: usize,
+    time: f32,
+    duration: f32,
+    elapsed: f32
+}
+
+// Plays the animation clips of a model, driving the transforms and morph weights of its nodes.
+pub struct AnimationPlayer {
+    pub clip: usize,
+    pub time: f32,
+    pub playing: bool,
+    pub looping: bool,
+    pub speed: f32,
+    cross_fade: Option<CrossFade>,
+    // Properties of the nodes before any clip was applied, used for everything a clip doesn't animate.
+    rest_pose: Vec<NodePose>
+}
+
+impl AnimationPlayer {
+    pub fn new(model: &Model) -> Self {
+        let rest_pose = model.nodes
+            .iter()
+            .map(|node| NodePose {
+                translation: node.translation,
+                rotation: node.rotation,
+                scale: node.scale,
+                weights: node.weights.clone()
+            })
+            .collect();
+
+        AnimationPlayer {
+            clip: 0,
+            time: 0.0,
+            playing: true,
+            looping: true,
+            speed: 1.0,
+            cross_fade: None,
+            rest_pose
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    // Blends from the current clip to `clip` over `duration` seconds, starting it from the beginning.
+    pub fn cross_fade(&mut self, clip: usize, duration: f32) {
+        self.cross_fade = Some(CrossFade {
+            clip,
+            time: 0.0,
+            duration: duration.max(1e-3),
+            elapsed: 0.0
+        });
+    }
+
+    fn advance(&self, time: f32, delta_time: f32, clip: &AnimationClip) -> f32 {
+        let time = time + delta_time * self.speed;
+        if !self.looping || clip.duration <= 0.0 {
+            time.clamp(0.0, clip.duration)
+        } else {
+            time.rem_euclid(clip.duration)
+        }
+    }
+
+    fn pose(&self, clip: &AnimationClip, time: f32) -> Vec<NodePose> {
+        let mut pose = self.rest_pose.clone();
+        clip.sample(time, &mut pose);
+        pose
+    }
+
+    // Advances playback by `delta_time` seconds and poses the nodes of `model`.
+    pub fn update(&mut self, model: &mut Model, delta_time: f32) {
+        let Some(clip) = model.animations.get(self.clip) else {
+            return;
+        };
+        let delta_time = if self.playing { delta_time } else { 0.0 };
+        self.time = self.advance(self.time, delta_time, clip);
+        let mut pose = self.pose(clip, self.time);
+
+        if let Some(cross_fade) = self.cross_fade.take() {
+            if let Some(target) = model.animations.get(cross_fade.clip) {
+                let time = self.advance(cross_fade.time, delta_time, target);
+                let elapsed = cross_fade.elapsed + delta_time;
+                let t = (elapsed / cross_fade.duration).min(1.0);
+                let target_pose = self.pose(target, time);
+                pose = pose.iter().zip(&target_pose).map(|(from, to)| from.blend(to, t)).collect();
+
+                if t >= 1.0 {
+                    self.clip = cross_fade.clip;
+                    self.time = time;
+                } else {
+                    self.cross_fade = Some(CrossFade { time, elapsed, ..cross_fade });
+                }
+            }
+        }
+
+        for (node, pose) in model.nodes.iter_mut().zip(pose) {
+            node.translation = pose.translation;
+            node.rotation = pose.rotation;
+            node.scale = pose.scale;
+            node.weights = pose.weights;
+        }
+    }
+}
@@ -0,0 +1,980 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    Bc1,
+    Bc1Alpha,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc4Signed,
+    Bc5,
+    Bc5Signed,
+    Bc6h,
+    Bc6hSigned,
+    Bc7
+}
+
+impl BlockFormat {
+    pub fn block_size(&self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc1Alpha | BlockFormat::Bc4 | BlockFormat::Bc4Signed => 8,
+            _ => 16
+        }
+    }
+}
+
+// Tightly packed RGBA texels of a decoded image.
+pub enum BlockPixels {
+    U8(Vec<u8>),
+    F32(Vec<f32>)
+}
+
+// Decodes a whole image of 4x4 blocks. BC6H keeps its full range as floats, the other formats are
+// decoded to RGBA8 with signed channels remapped from [-1, 1] to [0, 255].
+pub fn decode_blocks(format: BlockFormat, data: &[u8], width: usize, height: usize) -> BlockPixels {
+    match format {
+        BlockFormat::Bc6h | BlockFormat::Bc6hSigned => {
+            let signed = format == BlockFormat::Bc6hSigned;
+            BlockPixels::F32(decode_image(format, data, width, height, |block, texels| {
+                let mut hdr = [[0.0f32; 3]; 16];
+                decode_bc6h(block, &mut hdr, signed);
+                for (texel, [r, g, b]) in texels.iter_mut().zip(hdr) {
+                    *texel = [r, g, b, 1.0];
+                }
+            }))
+        },
+        _ => BlockPixels::U8(decode_image(format, data, width, height, |block, texels| decode_block(format, block, texels)))
+    }
+}
+
+fn decode_image<T: Copy + Default, F: FnMut(&[u8], &mut [[T; 4]; 16])>(
+    format: BlockFormat,
+    data: &[u8],
+    width: usize,
+    height: usize,
+    mut decode: F
+) -> Vec<T> {
+    let blocks_x = width.div_ceil(4);
+    let blocks_y = height.div_ceil(4);
+    let block_size = format.block_size();
+
+    let mut pixels = vec![T::default(); width * height * 4];
+    let mut texels = [[T::default(); 4]; 16];
+
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let offset = (by * blocks_x + bx) * block_size;
+            let block = &data[offset..offset + block_size];
+
+            decode(block, &mut texels);
+
+            for ty in 0..4 {
+                for tx in 0..4 {
+                    let x = bx * 4 + tx;
+                    let y = by * 4 + ty;
+                    if x < width && y < height {
+                        let dst = (y * width + x) * 4;
+                        pixels[dst..dst + 4].copy_from_slice(&texels[ty * 4 + tx]);
+                    }
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+// Decodes one block to RGBA8, clamping BC6H values to [0, 1].
+pub fn decode_block(format: BlockFormat, block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    match format {
+        BlockFormat::Bc1 => decode_bc1(block, texels, false, false),
+        BlockFormat::Bc1Alpha => decode_bc1(block, texels, true, false),
+        BlockFormat::Bc2 => {
+            decode_bc1(&block[8..16], texels, false, true);
+            let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+            for (i, texel) in texels.iter_mut().enumerate() {
+                let a = ((alpha >> (i * 4)) & 0xF) as u8;
+                texel[3] = a << 4 | a;
+            }
+        },
+        BlockFormat::Bc3 => {
+            decode_bc1(&block[8..16], texels, false, true);
+            let mut alpha = [0u8; 16];
+            decode_bc4_unorm(&block[0..8], &mut alpha);
+            for (texel, a) in texels.iter_mut().zip(alpha) {
+                texel[3] = a;
+            }
+        },
+        BlockFormat::Bc4 | BlockFormat::Bc4Signed => {
+            let mut red = [0u8; 16];
+            decode_bc4(&block[0..8], &mut red, format == BlockFormat::Bc4Signed);
+            for (texel, r) in texels.iter_mut().zip(red) {
+                *texel = [r, 0, 0, 255];
+            }
+        },
+        BlockFormat::Bc5 | BlockFormat::Bc5Signed => {
+            let signed = format == BlockFormat::Bc5Signed;
+            let mut red = [0u8; 16];
+            let mut green = [0u8; 16];
+            decode_bc4(&block[0..8], &mut red, signed);
+            decode_bc4(&block[8..16], &mut green, signed);
+            for (i, texel) in texels.iter_mut().enumerate() {
+                *texel = [red[i], green[i], 0, 255];
+            }
+        },
+        BlockFormat::Bc6h | BlockFormat::Bc6hSigned => {
+            let mut hdr = [[0.0f32; 3]; 16];
+            decode_bc6h(block, &mut hdr, format == BlockFormat::Bc6hSigned);
+            for (texel, rgb) in texels.iter_mut().zip(hdr) {
+                let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+                *texel = [to_u8(rgb[0]), to_u8(rgb[1]), to_u8(rgb[2]), 255];
+            }
+        },
+        BlockFormat::Bc7 => decode_bc7(block, texels)
+    }
+}
+
+fn expand_565(c: u16) -> [u8; 3] {
+    let r = ((c >> 11) & 0x1F) as u8;
+    let g = ((c >> 5) & 0x3F) as u8;
+    let b = (c & 0x1F) as u8;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+fn decode_bc1(block: &[u8], texels: &mut [[u8; 4]; 16], punch_through: bool, force_four_colors: bool) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let e0 = expand_565(c0);
+    let e1 = expand_565(c1);
+
+    let mut palette = [[0u8; 4]; 4];
+    palette[0] = [e0[0], e0[1], e0[2], 255];
+    palette[1] = [e1[0], e1[1], e1[2], 255];
+    if c0 > c1 || force_four_colors {
+        for c in 0..3 {
+            palette[2][c] = ((2 * e0[c] as u32 + e1[c] as u32) / 3) as u8;
+            palette[3][c] = ((e0[c] as u32 + 2 * e1[c] as u32) / 3) as u8;
+        }
+        palette[2][3] = 255;
+        palette[3][3] = 255;
+    } else {
+        for c in 0..3 {
+            palette[2][c] = ((e0[c] as u32 + e1[c] as u32) / 2) as u8;
+        }
+        palette[2][3] = 255;
+        palette[3] = [0, 0, 0, if punch_through { 0 } else { 255 }];
+    }
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (i * 2)) & 0x3) as usize];
+    }
+}
+
+fn decode_bc4(block: &[u8], values: &mut [u8; 16], signed: bool) {
+    if signed {
+        decode_bc4_snorm(block, values);
+    } else {
+        decode_bc4_unorm(block, values);
+    }
+}
+
+fn bc4_indices(block: &[u8]) -> u64 {
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    u64::from_le_bytes(bits)
+}
+
+fn decode_bc4_unorm(block: &[u8], values: &mut [u8; 16]) {
+    let a0 = block[0] as u32;
+    let a1 = block[1] as u32;
+
+    let mut palette = [0u8; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let indices = bc4_indices(block);
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[((indices >> (i * 3)) & 0x7) as usize];
+    }
+}
+
+fn decode_bc4_snorm(block: &[u8], values: &mut [u8; 16]) {
+    let a0 = (block[0] as i8).max(-127) as f32 / 127.0;
+    let a1 = (block[1] as i8).max(-127) as f32 / 127.0;
+
+    let mut palette = [0.0f32; 8];
+    palette[0] = a0;
+    palette[1] = a1;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i) as f32 * a0 + i as f32 * a1) / 7.0;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i) as f32 * a0 + i as f32 * a1) / 5.0;
+        }
+        palette[6] = -1.0;
+        palette[7] = 1.0;
+    }
+
+    let indices = bc4_indices(block);
+    for (i, value) in values.iter_mut().enumerate() {
+        let v = palette[((indices >> (i * 3)) & 0x7) as usize];
+        *value = ((v * 0.5 + 0.5) * 255.0 + 0.5) as u8;
+    }
+}
+
+struct BitReader {
+    bits: u128,
+    position: u32
+}
+
+impl BitReader {
+    fn new(block: &[u8]) -> Self {
+        BitReader {
+            bits: u128::from_le_bytes(block[0..16].try_into().unwrap()),
+            position: 0
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = ((self.bits >> self.position) & ((1u128 << count) - 1)) as u32;
+        self.position += count;
+        value
+    }
+
+    fn read_reversed(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.read(1) << (count - 1 - i);
+        }
+        value
+    }
+}
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4
+    }
+}
+
+// Bit i of each mask selects the subset of texel i.
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22
+];
+
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0]
+];
+
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15
+];
+
+const ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3
+];
+
+const ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8
+];
+
+fn subset_of(subset_count: usize, partition: usize, texel: usize) -> usize {
+    match subset_count {
+        2 => ((PARTITIONS_2[partition] >> texel) & 1) as usize,
+        3 => PARTITIONS_3[partition][texel] as usize,
+        _ => 0
+    }
+}
+
+fn is_anchor(subset_count: usize, partition: usize, texel: usize) -> bool {
+    texel == 0 || match subset_count {
+        2 => texel == ANCHORS_2[partition] as usize,
+        3 => texel == ANCHORS_3_SECOND[partition] as usize || texel == ANCHORS_3_THIRD[partition] as usize,
+        _ => false
+    }
+}
+
+fn read_indices(
+    reader: &mut BitReader,
+    index_bits: u32,
+    subset_count: usize,
+    partition: usize
+) -> [u32; 16] {
+    let mut indices = [0; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        let bits = if is_anchor(subset_count, partition, texel) { index_bits - 1 } else { index_bits };
+        *index = reader.read(bits);
+    }
+    indices
+}
+
+struct Bc7Mode {
+    subset_count: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subset_count: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 3 },
+    Bc7Mode { subset_count: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 2 },
+    Bc7Mode { subset_count: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, secondary_index_bits: 0 },
+    Bc7Mode { subset_count: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 }
+];
+
+fn interpolate(e0: u32, e1: u32, weight: u32) -> u8 {
+    (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+}
+
+fn decode_bc7(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    let mut reader = BitReader::new(block);
+
+    let mode_index = (block[0] as u32).trailing_zeros() as usize;
+    if mode_index >= 8 {
+        // Reserved mode, decoders must output transparent black.
+        *texels = [[0; 4]; 16];
+        return;
+    }
+    reader.read(mode_index as u32 + 1);
+    let mode = &BC7_MODES[mode_index];
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subset_count * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = if mode.alpha_bits > 0 { reader.read(mode.alpha_bits) } else { 255 };
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbits = [0u32; 6];
+        if mode.endpoint_pbits {
+            for pbit in pbits.iter_mut().take(endpoint_count) {
+                *pbit = reader.read(1);
+            }
+        } else {
+            for subset in 0..mode.subset_count {
+                let pbit = reader.read(1);
+                pbits[subset * 2] = pbit;
+                pbits[subset * 2 + 1] = pbit;
+            }
+        }
+
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits).take(endpoint_count) {
+            for channel in endpoint.iter_mut().take(3) {
+                *channel = *channel << 1 | pbit;
+            }
+            if mode.alpha_bits > 0 {
+                endpoint[3] = endpoint[3] << 1 | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for channel in endpoint.iter_mut().take(3) {
+            *channel = expand_bits(*channel, color_bits);
+        }
+        if alpha_bits > 0 {
+            endpoint[3] = expand_bits(endpoint[3], alpha_bits);
+        }
+    }
+
+    let primary = read_indices(&mut reader, mode.index_bits, mode.subset_count, partition);
+    let secondary = if mode.secondary_index_bits > 0 {
+        read_indices(&mut reader, mode.secondary_index_bits, 1, 0)
+    } else {
+        primary
+    };
+
+    let (color_indices, color_index_bits, alpha_indices, alpha_index_bits) = if mode.secondary_index_bits == 0 {
+        (&primary, mode.index_bits, &primary, mode.index_bits)
+    } else if index_selection == 0 {
+        (&primary, mode.index_bits, &secondary, mode.secondary_index_bits)
+    } else {
+        (&secondary, mode.secondary_index_bits, &primary, mode.index_bits)
+    };
+
+    for (texel, out) in texels.iter_mut().enumerate() {
+        let subset = subset_of(mode.subset_count, partition, texel);
+        let e0 = endpoints[subset * 2];
+        let e1 = endpoints[subset * 2 + 1];
+
+        let color_weight = weights(color_index_bits)[color_indices[texel] as usize];
+        let alpha_weight = weights(alpha_index_bits)[alpha_indices[texel] as usize];
+
+        let mut color = [
+            interpolate(e0[0], e1[0], color_weight),
+            interpolate(e0[1], e1[1], color_weight),
+            interpolate(e0[2], e1[2], color_weight),
+            interpolate(e0[3], e1[3], alpha_weight)
+        ];
+
+        match rotation {
+            1 => color.swap(0, 3),
+            2 => color.swap(1, 3),
+            3 => color.swap(2, 3),
+            _ => {}
+        }
+
+        *out = color;
+    }
+}
+
+fn expand_bits(value: u32, bits: u32) -> u32 {
+    if bits >= 8 {
+        value
+    } else {
+        let value = value << (8 - bits);
+        value | value >> bits
+    }
+}
+
+// Endpoint fields of a BC6H block, named as in the specification:
+// w/x are the endpoints of the first subset, y/z of the second.
+#[derive(Clone, Copy)]
+enum Field {
+    Rw, Gw, Bw,
+    Rx, Gx, Bx,
+    Ry, Gy, By,
+    Rz, Gz, Bz
+}
+
+use Field::*;
+
+struct Bc6hMode {
+    transformed: bool,
+    partitioned: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    // (field, first bit, bit count)
+    layout: &'static [(Field, u32, u32)],
+    // The 12.8 and 16.4 modes store the high bits of the base endpoint most significant bit first.
+    reversed: bool
+}
+
+const BC6H_MODE_0: &[(Field, u32, u32)] = &[
+    (Gy, 4, 1), (By, 4, 1), (Bz, 4, 1), (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10),
+    (Rx, 0, 5), (Gz, 4, 1), (Gy, 0, 4), (Gx, 0, 5), (Bz, 0, 1), (Gz, 0, 4),
+    (Bx, 0, 5), (Bz, 1, 1), (By, 0, 4), (Ry, 0, 5), (Bz, 2, 1), (Rz, 0, 5), (Bz, 3, 1)
+];
+const BC6H_MODE_1: &[(Field, u32, u32)] = &[
+    (Gy, 5, 1), (Gz, 4, 1), (Gz, 5, 1), (Rw, 0, 7), (Bz, 0, 1), (Bz, 1, 1), (By, 4, 1),
+    (Gw, 0, 7), (By, 5, 1), (Bz, 2, 1), (Gy, 4, 1), (Bw, 0, 7), (Bz, 3, 1), (Bz, 5, 1),
+    (Bz, 4, 1), (Rx, 0, 6), (Gy, 0, 4), (Gx, 0, 6), (Gz, 0, 4), (Bx, 0, 6), (By, 0, 4),
+    (Ry, 0, 6), (Rz, 0, 6)
+];
+const BC6H_MODE_2: &[(Field, u32, u32)] = &[
+    (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10), (Rx, 0, 5), (Rw, 10, 1), (Gy, 0, 4),
+    (Gx, 0, 4), (Gw, 10, 1), (Bz, 0, 1), (Gz, 0, 4), (Bx, 0, 4), (Bw, 10, 1),
+    (Bz, 1, 1), (By, 0, 4), (Ry, 0, 5), (Bz, 2, 1), (Rz, 0, 5), (Bz, 3, 1)
+];
+const BC6H_MODE_3: &[(Field, u32, u32)] = &[
+    (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10), (Rx, 0, 4), (Rw, 10, 1), (Gz, 4, 1),
+    (Gy, 0, 4), (Gx, 0, 5), (Gw, 10, 1), (Gz, 0, 4), (Bx, 0, 4), (Bw, 10, 1),
+    (Bz, 1, 1), (By, 0, 4), (Ry, 0, 4), (Bz, 0, 1), (Bz, 2, 1), (Rz, 0, 4),
+    (Gy, 4, 1), (Bz, 3, 1)
+];
+const BC6H_MODE_4: &[(Field, u32, u32)] = &[
+    (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10), (Rx, 0, 4), (Rw, 10, 1), (By, 4, 1),
+    (Gy, 0, 4), (Gx, 0, 4), (Gw, 10, 1), (Bz, 0, 1), (Gz, 0, 4), (Bx, 0, 5),
+    (Bw, 10, 1), (By, 0, 4), (Ry, 0, 4), (Bz, 1, 1), (Bz, 2, 1), (Rz, 0, 4),
+    (Bz, 4, 1), (Bz, 3, 1)
+];
+const BC6H_MODE_5: &[(Field, u32, u32)] = &[
+    (Rw, 0, 9), (By, 4, 1), (Gw, 0, 9), (Gy, 4, 1), (Bw, 0, 9), (Bz, 4, 1),
+    (Rx, 0, 5), (Gz, 4, 1), (Gy, 0, 4), (Gx, 0, 5), (Bz, 0, 1), (Gz, 0, 4),
+    (Bx, 0, 5), (Bz, 1, 1), (By, 0, 4), (Ry, 0, 5), (Bz, 2, 1), (Rz, 0, 5), (Bz, 3, 1)
+];
+const BC6H_MODE_6: &[(Field, u32, u32)] = &[
+    (Rw, 0, 8), (Gz, 4, 1), (By, 4, 1), (Gw, 0, 8), (Bz, 2, 1), (Gy, 4, 1),
+    (Bw, 0, 8), (Bz, 3, 1), (Bz, 4, 1), (Rx, 0, 6), (Gy, 0, 4), (Gx, 0, 5),
+    (Bz, 0, 1), (Gz, 0, 4), (Bx, 0, 5), (Bz, 1, 1), (By, 0, 4), (Ry, 0, 6), (Rz, 0, 6)
+];
+const BC6H_MODE_7: &[(Field, u32, u32)] = &[
+    (Rw, 0, 8), (Bz, 0, 1), (By, 4, 1), (Gw, 0, 8), (Gy, 5, 1), (Gy, 4, 1),
+    (Bw, 0, 8), (Gz, 5, 1), (Bz, 4, 1), (Rx, 0, 5), (Gz, 4, 1), (Gy, 0, 4),
+    (Gx, 0, 6), (Gz, 0, 4), (Bx, 0, 5), (Bz, 1, 1), (By, 0, 4), (Ry, 0, 5),
+    (Bz, 2, 1), (Rz, 0, 5), (Bz, 3, 1)
+];
+const BC6H_MODE_8: &[(Field, u32, u32)] = &[
+    (Rw, 0, 8), (Bz, 1, 1), (By, 4, 1), (Gw, 0, 8), (By, 5, 1), (Gy, 4, 1),
+    (Bw, 0, 8), (Bz, 5, 1), (Bz, 4, 1), (Rx, 0, 5), (Gz, 4, 1), (Gy, 0, 4),
+    (Gx, 0, 5), (Bz, 0, 1), (Gz, 0, 4), (Bx, 0, 6), (By, 0, 4), (Ry, 0, 5),
+    (Bz, 2, 1), (Rz, 0, 5), (Bz, 3, 1)
+];
+const BC6H_MODE_9: &[(Field, u32, u32)] = &[
+    (Rw, 0, 6), (Gz, 4, 1), (Bz, 0, 1), (Bz, 1, 1), (By, 4, 1), (Gw, 0, 6),
+    (Gy, 5, 1), (By, 5, 1), (Bz, 2, 1), (Gy, 4, 1), (Bw, 0, 6), (Gz, 5, 1),
+    (Bz, 3, 1), (Bz, 5, 1), (Bz, 4, 1), (Rx, 0, 6), (Gy, 0, 4), (Gx, 0, 6),
+    (Gz, 0, 4), (Bx, 0, 6), (By, 0, 4), (Ry, 0, 6), (Rz, 0, 6)
+];
+const BC6H_MODE_10: &[(Field, u32, u32)] = &[
+    (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10), (Rx, 0, 10), (Gx, 0, 10), (Bx, 0, 10)
+];
+const BC6H_MODE_11: &[(Field, u32, u32)] = &[
+    (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10), (Rx, 0, 9), (Rw, 10, 1),
+    (Gx, 0, 9), (Gw, 10, 1), (Bx, 0, 9), (Bw, 10, 1)
+];
+const BC6H_MODE_12: &[(Field, u32, u32)] = &[
+    (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10), (Rx, 0, 8), (Rw, 10, 2),
+    (Gx, 0, 8), (Gw, 10, 2), (Bx, 0, 8), (Bw, 10, 2)
+];
+const BC6H_MODE_13: &[(Field, u32, u32)] = &[
+    (Rw, 0, 10), (Gw, 0, 10), (Bw, 0, 10), (Rx, 0, 4), (Rw, 10, 6),
+    (Gx, 0, 4), (Gw, 10, 6), (Bx, 0, 4), (Bw, 10, 6)
+];
+
+fn bc6h_mode(mode_bits: u32) -> Option<Bc6hMode> {
+    let mode = |transformed, partitioned, endpoint_bits, delta_bits, layout| Bc6hMode {
+        transformed,
+        partitioned,
+        endpoint_bits,
+        delta_bits,
+        layout,
+        reversed: false
+    };
+
+    match mode_bits {
+        0b00 => Some(mode(true, true, 10, [5, 5, 5], BC6H_MODE_0)),
+        0b01 => Some(mode(true, true, 7, [6, 6, 6], BC6H_MODE_1)),
+        0b00010 => Some(mode(true, true, 11, [5, 4, 4], BC6H_MODE_2)),
+        0b00110 => Some(mode(true, true, 11, [4, 5, 4], BC6H_MODE_3)),
+        0b01010 => Some(mode(true, true, 11, [4, 4, 5], BC6H_MODE_4)),
+        0b01110 => Some(mode(true, true, 9, [5, 5, 5], BC6H_MODE_5)),
+        0b10010 => Some(mode(true, true, 8, [6, 5, 5], BC6H_MODE_6)),
+        0b10110 => Some(mode(true, true, 8, [5, 6, 5], BC6H_MODE_7)),
+        0b11010 => Some(mode(true, true, 8, [5, 5, 6], BC6H_MODE_8)),
+        0b11110 => Some(mode(false, true, 6, [6, 6, 6], BC6H_MODE_9)),
+        0b00011 => Some(mode(false, false, 10, [10, 10, 10], BC6H_MODE_10)),
+        0b00111 => Some(mode(true, false, 11, [9, 9, 9], BC6H_MODE_11)),
+        0b01011 => Some(Bc6hMode { reversed: true, ..mode(true, false, 12, [8, 8, 8], BC6H_MODE_12) }),
+        0b01111 => Some(Bc6hMode { reversed: true, ..mode(true, false, 16, [4, 4, 4], BC6H_MODE_13) }),
+        _ => None
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+fn unquantize_bc6h(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else {
+        if bits >= 16 {
+            return value;
+        }
+        let negative = value < 0;
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if negative { -unquantized } else { unquantized }
+    }
+}
+
+fn finish_unquantize_bc6h(value: i32, signed: bool) -> f32 {
+    let half = if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    };
+    half_to_f32(half)
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2.0f32.powi(-24),
+        31 => if mantissa == 0.0 { f32::INFINITY } else { f32::NAN },
+        _ => (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15)
+    }
+}
+
+fn decode_bc6h(block: &[u8], texels: &mut [[f32; 3]; 16], signed: bool) {
+    let mut reader = BitReader::new(block);
+
+    let mut mode_bits = reader.read(2);
+    if mode_bits > 1 {
+        mode_bits |= reader.read(3) << 2;
+    }
+
+    let mode = match bc6h_mode(mode_bits) {
+        Some(mode) => mode,
+        None => {
+            *texels = [[0.0; 3]; 16];
+            return;
+        }
+    };
+
+    // Endpoints indexed as [w, x, y, z][channel].
+    let mut endpoints = [[0u32; 3]; 4];
+    for &(field, first_bit, count) in mode.layout {
+        let value = if mode.reversed && first_bit >= 10 {
+            reader.read_reversed(count)
+        } else {
+            reader.read(count)
+        };
+
+        let (endpoint, channel) = match field {
+            Rw => (0, 0), Gw => (0, 1), Bw => (0, 2),
+            Rx => (1, 0), Gx => (1, 1), Bx => (1, 2),
+            Ry => (2, 0), Gy => (2, 1), By => (2, 2),
+            Rz => (3, 0), Gz => (3, 1), Bz => (3, 2)
+        };
+        endpoints[endpoint][channel] |= value << first_bit;
+    }
+
+    let partition = if mode.partitioned { reader.read(5) as usize } else { 0 };
+    let endpoint_count = if mode.partitioned { 4 } else { 2 };
+
+    let mut values = [[0i32; 3]; 4];
+    let endpoint_mask = (1u32 << mode.endpoint_bits) - 1;
+    for channel in 0..3 {
+        let base = endpoints[0][channel];
+        values[0][channel] = if signed {
+            sign_extend(base, mode.endpoint_bits)
+        } else {
+            base as i32
+        };
+
+        for endpoint in 1..endpoint_count {
+            let raw = endpoints[endpoint][channel];
+            let value = if mode.transformed {
+                let delta = sign_extend(raw, mode.delta_bits[channel]);
+                (base as i32 + delta) as u32 & endpoint_mask
+            } else {
+                raw
+            };
+            values[endpoint][channel] = if signed {
+                sign_extend(value, mode.endpoint_bits)
+            } else {
+                value as i32
+            };
+        }
+    }
+
+    for endpoint in values.iter_mut().take(endpoint_count) {
+        for channel in endpoint.iter_mut() {
+            *channel = unquantize_bc6h(*channel, mode.endpoint_bits, signed);
+        }
+    }
+
+    let subset_count = if mode.partitioned { 2 } else { 1 };
+    let index_bits = if mode.partitioned { 3 } else { 4 };
+    let indices = read_indices(&mut reader, index_bits, subset_count, partition);
+
+    for (texel, out) in texels.iter_mut().enumerate() {
+        let subset = subset_of(subset_count, partition, texel);
+        let e0 = values[subset * 2];
+        let e1 = values[subset * 2 + 1];
+        let weight = weights(index_bits)[indices[texel] as usize] as i32;
+
+        for channel in 0..3 {
+            let value = ((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6;
+            out[channel] = finish_unquantize_bc6h(value, signed);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Packs fields LSB first, the order block bits are read in.
+    struct BitWriter {
+        bits: u128,
+        position: u32
+    }
+
+    impl BitWriter {
+        fn new() -> Self {
+            BitWriter { bits: 0, position: 0 }
+        }
+
+        fn write(&mut self, value: u32, count: u32) {
+            self.bits |= ((value as u128) & ((1 << count) - 1)) << self.position;
+            self.position += count;
+        }
+
+        fn finish(self) -> [u8; 16] {
+            assert_eq!(self.position, 128);
+            self.bits.to_le_bytes()
+        }
+    }
+
+    fn decode(format: BlockFormat, block: &[u8]) -> [[u8; 4]; 16] {
+        let mut texels = [[0; 4]; 16];
+        decode_block(format, block, &mut texels);
+        texels
+    }
+
+    // A BC4 block whose texel i uses palette index i % 8.
+    fn bc4_block(a0: u8, a1: u8) -> [u8; 8] {
+        let indices = (0..16).fold(0u64, |bits, i| bits | ((i % 8) as u64) << (i * 3));
+        let mut block = [0; 8];
+        block[0] = a0;
+        block[1] = a1;
+        block[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+        block
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        // Red and blue endpoints, texel i uses index i % 4.
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4];
+        let texels = decode(BlockFormat::Bc1, &block);
+        let palette = [[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255]];
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, palette[i % 4]);
+        }
+    }
+
+    #[test]
+    fn bc1_three_colors() {
+        // c0 <= c1 selects the midpoint and black, which is transparent when alpha is used.
+        let block = [0x00, 0x00, 0x10, 0x84, 0xE4, 0xE4, 0xE4, 0xE4];
+        let texels = decode(BlockFormat::Bc1, &block);
+        assert_eq!(texels[0], [0, 0, 0, 255]);
+        assert_eq!(texels[1], [132, 130, 132, 255]);
+        assert_eq!(texels[2], [66, 65, 66, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 255]);
+
+        let texels = decode(BlockFormat::Bc1Alpha, &block);
+        assert_eq!(texels[2], [66, 65, 66, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc3_alpha() {
+        // White color block, which BC3 decodes with four colors even though c0 == c1.
+        let color = [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00];
+
+        let mut block = [0; 16];
+        block[0..8].copy_from_slice(&bc4_block(70, 0));
+        block[8..16].copy_from_slice(&color);
+        let alphas = [70, 0, 60, 50, 40, 30, 20, 10];
+        for (i, texel) in decode(BlockFormat::Bc3, &block).iter().enumerate() {
+            assert_eq!(*texel, [255, 255, 255, alphas[i % 8]]);
+        }
+
+        // a0 <= a1 interpolates 4 values and adds 0 and 255.
+        block[0..8].copy_from_slice(&bc4_block(0, 50));
+        let alphas = [0, 50, 10, 20, 30, 40, 0, 255];
+        for (i, texel) in decode(BlockFormat::Bc3, &block).iter().enumerate() {
+            assert_eq!(*texel, [255, 255, 255, alphas[i % 8]]);
+        }
+    }
+
+    #[test]
+    fn bc5_red_green() {
+        let mut block = [0; 16];
+        block[0..8].copy_from_slice(&bc4_block(70, 0));
+        block[8..16].copy_from_slice(&bc4_block(0, 50));
+        let reds = [70, 0, 60, 50, 40, 30, 20, 10];
+        let greens = [0, 50, 10, 20, 30, 40, 0, 255];
+        for (i, texel) in decode(BlockFormat::Bc5, &block).iter().enumerate() {
+            assert_eq!(*texel, [reds[i % 8], greens[i % 8], 0, 255]);
+        }
+
+        // Signed endpoints of 1 and -1 map to the ends of the unsigned range.
+        block[0..8].copy_from_slice(&bc4_block(0x7F, 0x81));
+        let texels = decode(BlockFormat::Bc5Signed, &block);
+        assert_eq!(texels[0][0], 255);
+        assert_eq!(texels[1][0], 0);
+    }
+
+    #[test]
+    fn bc7_mode_1_partitioned() {
+        let mut writer = BitWriter::new();
+        writer.write(0b10, 2);
+        // Partition 0 puts the two right columns in the second subset.
+        writer.write(0, 6);
+        // Red, green and blue of the 4 endpoints: red to gray in subset 0, green to blue in subset 1.
+        for value in [63, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 63] {
+            writer.write(value, 6);
+        }
+        // Shared p-bits of both subsets.
+        writer.write(1, 1);
+        writer.write(0, 1);
+        // Texel 0 and 15 are the subset anchors with an implied leading zero.
+        writer.write(0, 2);
+        writer.write(4, 3);
+        writer.write(7, 3);
+        for _ in 3..15 {
+            writer.write(0, 3);
+        }
+        writer.write(0, 2);
+        let texels = decode(BlockFormat::Bc7, &writer.finish());
+
+        assert_eq!(texels[0], [255, 2, 2, 255]);
+        assert_eq!(texels[1], [109, 2, 2, 255]);
+        assert_eq!(texels[2], [0, 0, 253, 255]);
+        assert_eq!(texels[3], [0, 253, 0, 255]);
+        assert_eq!(texels[4], [255, 2, 2, 255]);
+        assert_eq!(texels[15], [0, 253, 0, 255]);
+    }
+
+    // Mode 10, one subset with 10 bit endpoints that are not delta coded.
+    fn bc6h_mode_10_block(e0: [u32; 3], e1: [u32; 3], indices: [u32; 3]) -> [u8; 16] {
+        let mut writer = BitWriter::new();
+        writer.write(0b11, 2);
+        writer.write(0, 3);
+        for value in e0.into_iter().chain(e1) {
+            writer.write(value, 10);
+        }
+        writer.write(indices[0], 3);
+        writer.write(indices[1], 4);
+        writer.write(indices[2], 4);
+        for _ in 3..16 {
+            writer.write(0, 4);
+        }
+        writer.finish()
+    }
+
+    fn decode_hdr(block: &[u8], signed: bool) -> [[f32; 3]; 16] {
+        let mut texels = [[0.0; 3]; 16];
+        decode_bc6h(block, &mut texels, signed);
+        texels
+    }
+
+    #[test]
+    fn bc6h_unsigned() {
+        // 495 unquantizes to half 1.0 and 1023 to the largest half.
+        let block = bc6h_mode_10_block([495, 1023, 0], [0, 0, 495], [0, 15, 8]);
+        let texels = decode_hdr(&block, false);
+        assert_eq!(texels[0], [1.0, 65504.0, 0.0]);
+        assert_eq!(texels[1], [0.0, 0.0, 1.0]);
+        assert_eq!(texels[2], [0.0040283203, 0.765625, 0.0076904297]);
+        assert_eq!(texels[3], [1.0, 65504.0, 0.0]);
+
+        assert_eq!(decode(BlockFormat::Bc6h, &block)[0], [255, 255, 0, 255]);
+    }
+
+    #[test]
+    fn bc6h_keeps_range_above_one() {
+        // 529 unquantizes to half 0x401E, which is 2.0585938.
+        let block = bc6h_mode_10_block([529, 1023, 0], [0, 0, 0], [0, 0, 0]);
+        let BlockPixels::F32(pixels) = decode_blocks(BlockFormat::Bc6h, &block, 4, 4) else {
+            panic!("BC6H decoded to 8 bit texels");
+        };
+        assert_eq!(pixels[0..4], [2.0585938, 65504.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn bc6h_signed() {
+        // 0x200 is -512 once sign extended, so both ends saturate.
+        let block = bc6h_mode_10_block([0x1FF, 0, 0x200], [0x200, 0x1FF, 0], [0, 15, 0]);
+        let texels = decode_hdr(&block, true);
+        assert_eq!(texels[0], [65504.0, 0.0, -65504.0]);
+        assert_eq!(texels[1], [-65504.0, 65504.0, 0.0]);
+
+        assert_eq!(decode(BlockFormat::Bc6hSigned, &block)[1], [0, 255, 0, 255]);
+    }
+}
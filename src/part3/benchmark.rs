@@ -0,0 +1,51 @@
+use glam::*;
+use std::time::{Duration, Instant};
+use crate::{Texture, TextureLayout};
+
+const SCREEN_SIZE: usize = 2048;
+const ANGLES: [f32; 5] = [0.0, 30.0, 45.0, 60.0, 90.0];
+const ITERATIONS: usize = 3;
+
+// Samples `texture` at one texel per pixel over a large screen, rotated by `angle` degrees,
+// which is the access pattern of a close-up of a textured surface spinning in front of the camera.
+fn sample_rotated(texture: &Texture, angle: f32) -> Duration {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let texel_size = 1.0 / texture.width() as f32;
+
+    let mut best = Duration::MAX;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        let mut sum = Vec4::ZERO;
+
+        for y in 0..SCREEN_SIZE {
+            for x in 0..SCREEN_SIZE {
+                let p = Vec2::new(x as f32, y as f32) - SCREEN_SIZE as f32 * 0.5;
+                let uv = Vec2::new(p.x * cos - p.y * sin, p.x * sin + p.y * cos) * texel_size + 0.5;
+                sum += texture.sample_pixel(uv.x, uv.y);
+            }
+        }
+
+        std::hint::black_box(sum);
+        best = best.min(start.elapsed());
+    }
+
+    best
+}
+
+pub fn run_texture_layout_benchmark(texture: &Texture) {
+    print!("{:<10}", "layout");
+    for angle in ANGLES {
+        print!("{:>10}", format!("{}deg", angle));
+    }
+    println!();
+
+    for layout in [TextureLayout::Linear, TextureLayout::Tiled4x4, TextureLayout::Tiled8x8, TextureLayout::Morton] {
+        let texture = texture.clone().with_layout(layout);
+
+        print!("{:<10}", format!("{:?}", layout));
+        for angle in ANGLES {
+            print!("{:>10}", format!("{:.2}ms", sample_rotated(&texture, angle).as_secs_f64() * 1000.0));
+        }
+        println!();
+    }
+}
@@ -0,0 +1,93 @@
+use glam::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    // Vertical field of view in radians, the far plane is at infinity if `far` is `None`.
+    Perspective { fov_y: f32, near: f32, far: Option<f32> },
+    // Half the height of the view volume, its width follows from the aspect ratio.
+    Orthographic { y_mag: f32, near: f32, far: f32 }
+}
+
+// A viewpoint looking down its local -Z axis, with +Y up.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub projection: Projection,
+    // Width over height of the viewport, kept up to date with `resize`.
+    pub aspect_ratio: f32,
+    // Transform from the camera's space to world space.
+    pub transform: Mat4
+}
+
+impl Camera {
+    pub fn new(projection: Projection) -> Self {
+        Camera {
+            projection,
+            aspect_ratio: 1.0,
+            transform: Mat4::IDENTITY
+        }
+    }
+
+    // Creates a camera from a glTF camera attached to a node with the given transform. The aspect ratio
+    // of the file is ignored in favor of the viewport's so the image isn't stretched.
+    pub fn from_gltf(camera: &gltf::Camera, transform: &Mat4) -> Self {
+        let projection = match camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) => Projection::Perspective {
+                fov_y: perspective.yfov(),
+                near: perspective.znear(),
+                far: perspective.zfar()
+            },
+            gltf::camera::Projection::Orthographic(orthographic) => Projection::Orthographic {
+                y_mag: orthographic.ymag(),
+                near: orthographic.znear(),
+                far: orthographic.zfar()
+            }
+        };
+
+        Camera {
+            transform: *transform,
+            ..Camera::new(projection)
+        }
+    }
+
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Camera {
+            transform: *transform * self.transform,
+            ..*self
+        }
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.aspect_ratio = width as f32 / height.max(1) as f32;
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.transform.transform_point3(Vec3::ZERO)
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        self.transform.inverse()
+    }
+
+    pub fn projection_matrix(&self) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { fov_y, near, far: Some(far) } => Mat4::perspective_rh(fov_y, self.aspect_ratio, near, far),
+            Projection::Perspective { fov_y, near, far: None } => Mat4::perspective_infinite_rh(fov_y, self.aspect_ratio, near),
+            Projection::Orthographic { y_mag, near, far } => {
+                let x_mag = y_mag * self.aspect_ratio;
+                Mat4::orthographic_rh(-x_mag, x_mag, -y_mag, y_mag, near, far)
+            }
+        }
+    }
+
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    // Distances to the near and far planes, the far one may be infinite.
+    pub fn near_far(&self) -> (f32, f32) {
+        match self.projection {
+            Projection::Perspective { near, far, .. } => (near, far.unwrap_or(f32::INFINITY)),
+            Projection::Orthographic { near, far, .. } => (near, far)
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use glam::*;
+use crate::{Camera, Light, MeshInstance, ShadowMap, ShadowSettings};
+use crate::shadow::{ShadowProjection, look_at};
+
+// Shadow maps for a directional light, each covering a successive depth range of the camera frustum.
+pub struct CascadedShadowMap {
+    cascades: Vec<ShadowMap>,
+    // View space distances bounding the cascades, one more than there are cascades.
+    splits: Vec<f32>,
+    camera_view: Mat4,
+    blend: f32
+}
+
+fn split_distances(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (0..=count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(t);
+            let uniform = near + (far - near) * t;
+            uniform + (logarithmic - uniform) * lambda
+        })
+        .collect()
+}
+
+impl CascadedShadowMap {
+    // Renders one cascade per split of the part of the camera frustum that overlaps the world space sphere `bounds`.
+    pub fn render(
+        light: &Light,
+        settings: &ShadowSettings,
+        instances: &[MeshInstance],
+        model_matrix: &Mat4,
+        bounds: (Vec3, f32),
+        camera: &Camera
+    ) -> Self {
+        let (scene_center, scene_radius) = bounds;
+        let distance = camera.position().distance(scene_center);
+
+        let (near, far) = camera.near_far();
+        let shadow_near = near.max(distance - scene_radius);
+        let shadow_far = far.min(distance + scene_radius).max(shadow_near + 1e-3);
+        let count = settings.cascade_count.max(1);
+        let splits = split_distances(shadow_near, shadow_far, count, settings.cascade_split_lambda);
+
+        // Corners of the view volume at a view space distance, found by unprojecting its depth so that
+        // infinite far planes and orthographic projections work too.
+        let camera_proj = camera.projection_matrix();
+        let inv_view_proj = camera.view_projection_matrix().inverse();
+        let corners_at = |distance: f32| {
+            let depth = camera_proj.project_point3(Vec3::new(0.0, 0.0, -distance)).z;
+            [Vec2::new(-1.0, -1.0), Vec2::new(1.0, -1.0), Vec2::new(-1.0, 1.0), Vec2::new(1.0, 1.0)]
+                .map(|xy| inv_view_proj.project_point3(Vec3::from((xy, depth))))
+        };
+
+        let light_rotation = look_at(Vec3::ZERO, light.direction);
+        let inv_light_rotation = light_rotation.inverse();
+
+        let cascades = splits
+            .windows(2)
+            .map(|split| {
+                let slice: Vec<Vec3> = split.iter().flat_map(|distance| corners_at(*distance)).collect();
+
+                // A bounding sphere keeps the cascade size constant while the camera rotates.
+                let center = slice.iter().fold(Vec3::ZERO, |sum, corner| sum + *corner) / slice.len() as f32;
+                let radius = slice.iter().map(|corner| corner.distance(center)).fold(0.0, f32::max).max(1e-3);
+
+                // Snap the center to whole texels in light space so edges don't shimmer as the camera moves.
+                let texel_size = 2.0 * radius / settings.resolution as f32;
+                let center_light_space = light_rotation.transform_point3(center);
+                let snapped = (center_light_space.xy() / texel_size).floor() * texel_size;
+                let center = inv_light_rotation.transform_point3(Vec3::from((snapped, center_light_space.z)));
+
+                // Extend the depth range towards the light so casters outside the slice are included.
+                let scene_offset = (scene_center - center).dot(light.direction);
+                let start = (scene_offset - scene_radius).min(-radius);
+                let end = (scene_offset + scene_radius).max(radius);
+
+                let view = look_at(center + light.direction * (start - 1e-3), light.direction);
+                let projection = ShadowProjection::Orthographic { near: 0.0, far: end - start + 2e-3, size: 2.0 * radius };
+                ShadowMap::render_view(&view, projection, settings, instances, model_matrix)
+            })
+            .collect();
+
+        CascadedShadowMap {
+            cascades,
+            splits,
+            camera_view: camera.view_matrix(),
+            blend: settings.cascade_blend
+        }
+    }
+
+    // Index of the cascade covering `world_position`, if any.
+    pub fn cascade(&self, world_position: &Vec3) -> Option<usize> {
+        let depth = -self.camera_view.transform_point3(*world_position).z;
+        self.splits.windows(2).position(|split| depth < split[1])
+    }
+
+    pub fn visibility(&self, world_position: &Vec3) -> f32 {
+        let Some(i) = self.cascade(world_position) else {
+            return 1.0;
+        };
+
+        let visibility = self.cascades[i].visibility(world_position);
+        if i + 1 == self.cascades.len() {
+            return visibility;
+        }
+
+        // Fade into the next cascade towards the end of this one to hide the change in resolution.
+        let depth = -self.camera_view.transform_point3(*world_position).z;
+        let (start, end) = (self.splits[i], self.splits[i + 1]);
+        let blend_start = end - self.blend * (end - start);
+        if depth <= blend_start {
+            return visibility;
+        }
+
+        let t = (depth - blend_start) / (end - blend_start);
+        visibility + (self.cascades[i + 1].visibility(world_position) - visibility) * t
+    }
+}
@@ -0,0 +1,127 @@
+use glam::*;
+use std::f32::consts::FRAC_PI_2;
+use crate::{Cubemap, Light, MeshInstance, ShadowMap, ShadowSettings, ShadowFilter};
+use crate::cubemap::direction_to_face;
+use crate::shadow::{ShadowProjection, POISSON_DISC, MAX_BLOCKER_SEARCH_RADIUS, look_at, random_angle};
+
+// View axes of the six faces, in the same order as cubemap faces.
+const FACE_AXES: [Vec3; 6] = [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z];
+
+// Omnidirectional shadows for a point light, storing the distance to the nearest occluder in every direction.
+pub struct CubeShadowMap {
+    distances: Cubemap,
+    position: Vec3,
+    // Angle covered by a texel at the center of a face.
+    texel_angle: f32,
+    // Distance comparisons are offset by `constant_bias + slope_bias * distance`.
+    constant_bias: f32,
+    slope_bias: f32,
+    filter: ShadowFilter
+}
+
+impl CubeShadowMap {
+    // Renders the six faces around `light`, reaching up to its range or the far side of the world space sphere `bounds`.
+    pub fn render(
+        light: &Light,
+        settings: &ShadowSettings,
+        instances: &[MeshInstance],
+        model_matrix: &Mat4,
+        bounds: (Vec3, f32)
+    ) -> Self {
+        let (center, radius) = bounds;
+        let far = light.range.unwrap_or(light.position.distance(center) + radius).max(1e-2);
+        let projection = ShadowProjection::Perspective { near: far * 1e-2, far, fov: FRAC_PI_2 };
+
+        // Perspective depth is too unevenly distributed for a constant bias, so it is applied to distances instead.
+        let face_settings = ShadowSettings {
+            depth_bias: 0.0,
+            slope_bias: 0.0,
+            ..*settings
+        };
+        let faces = FACE_AXES.map(|axis| {
+            ShadowMap::render_view(&look_at(light.position, axis), projection, &face_settings, instances, model_matrix)
+        });
+
+        let distances = Cubemap::from_fn_single_channel(settings.resolution as u32, |dir| {
+            let (face, _, _) = direction_to_face(&dir);
+            faces[face]
+                .occluder_distance(&(light.position + dir))
+                .map_or(far, |distance| distance / dir.dot(FACE_AXES[face]))
+        });
+
+        let texel_angle = 2.0 / settings.resolution as f32;
+        CubeShadowMap {
+            distances,
+            position: light.position,
+            texel_angle,
+            constant_bias: settings.depth_bias * far,
+            slope_bias: settings.slope_bias * texel_angle,
+            filter: settings.filter
+        }
+    }
+
+    fn occluder_distance(&self, dir: &Vec3) -> f32 {
+        self.distances.get_pixel(dir).x
+    }
+
+    fn compare(&self, dir: &Vec3, distance: f32) -> f32 {
+        let bias = self.constant_bias + self.slope_bias * distance;
+        if distance - bias > self.occluder_distance(dir) { 0.0 } else { 1.0 }
+    }
+
+    // Directions on a randomly rotated Poisson disc of `radius` texels around `dir`.
+    fn disc(&self, dir: Vec3, radius: f32) -> impl Iterator<Item = Vec3> {
+        let rotation = Vec2::from_angle(random_angle(dir.xy() * 1000.0 + dir.z));
+        let tangent = dir.any_orthonormal_vector();
+        let bitangent = dir.cross(tangent);
+        let scale = radius * self.texel_angle;
+
+        POISSON_DISC.iter().map(move |offset| {
+            let offset = rotation.rotate(*offset) * scale;
+            (dir + tangent * offset.x + bitangent * offset.y).normalize()
+        })
+    }
+
+    fn filtered(&self, dir: Vec3, distance: f32, radius: f32) -> f32 {
+        let lit: f32 = self.disc(dir, radius).map(|sample| self.compare(&sample, distance)).sum();
+        lit / POISSON_DISC.len() as f32
+    }
+
+    // Fraction of the light reaching `world_position`, 0 when it is fully shadowed.
+    pub fn visibility(&self, world_position: &Vec3) -> f32 {
+        let to_position = *world_position - self.position;
+        let distance = to_position.length();
+        if distance <= 0.0 {
+            return 1.0;
+        }
+        let dir = to_position / distance;
+
+        match self.filter {
+            ShadowFilter::Hard => self.compare(&dir, distance),
+            ShadowFilter::Pcf { kernel_size } => self.filtered(dir, distance, kernel_size as f32 * 0.5),
+            ShadowFilter::Poisson { radius } => self.filtered(dir, distance, radius),
+            ShadowFilter::Pcss { light_size } => {
+                // Widest penumbra possible, for a blocker right at the light.
+                let texel_size = distance * self.texel_angle;
+                let search_radius = (light_size / texel_size).clamp(1.0, MAX_BLOCKER_SEARCH_RADIUS);
+
+                let mut blocker_distance = 0.0;
+                let mut blocker_count = 0;
+                for sample in self.disc(dir, search_radius) {
+                    let occluder = self.occluder_distance(&sample);
+                    if occluder < distance - self.constant_bias - self.slope_bias * distance {
+                        blocker_distance += occluder;
+                        blocker_count += 1;
+                    }
+                }
+                if blocker_count == 0 {
+                    return 1.0;
+                }
+
+                let blocker = blocker_distance / blocker_count as f32;
+                let penumbra = light_size * (distance - blocker) / blocker;
+                self.filtered(dir, distance, (penumbra / texel_size).max(1.0))
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use glam::*;
+use std::f32::consts::PI;
+use std::path::Path;
+use crate::{Texture, ColorSpace, load_texture, LoadError};
+
+// Faces are ordered +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Clone, Debug)]
+pub struct Cubemap {
+    faces: [Texture; 6]
+}
+
+pub fn load_cubemap<P: AsRef<Path>>(face_paths: &[P; 6]) -> Result<Cubemap, LoadError> {
+    let mut faces: Vec<Texture> = Vec::with_capacity(6);
+    // 8 bit faces hold sRGB colors, float images are always linear.
+    for face_path in face_paths {
+        let face = load_texture(face_path)?.with_default_color_space(ColorSpace::Srgb);
+        // Sampling assumes every face has the size of the first one.
+        let face_size = faces.first().unwrap_or(&face).width();
+        if face.width() != face_size || face.height() != face_size {
+            return Err(LoadError::ImageDecode {
+                path: face_path.as_ref().to_path_buf(),
+                reason: format!("cubemap face is {}x{}, expected {}x{}", face.width(), face.height(), face_size, face_size)
+            });
+        }
+        faces.push(face);
+    }
+
+    Ok(Cubemap {
+        faces: faces.try_into().unwrap()
+    })
+}
+
+pub fn load_equirectangular_cubemap<P: AsRef<Path>>(file_path: P) -> Result<Cubemap, LoadError> {
+    let texture = load_texture(file_path)?.with_default_color_space(ColorSpace::Srgb);
+    Ok(Cubemap::from_equirectangular(&texture, (texture.width() / 4).max(1)))
+}
+
+fn face_direction(face: usize, u: f32, v: f32) -> Vec3 {
+    let s = u * 2.0 - 1.0;
+    let t = v * 2.0 - 1.0;
+
+    match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0)
+    }.normalize()
+}
+
+pub(crate) fn direction_to_face(dir: &Vec3) -> (usize, f32, f32) {
+    let abs = dir.abs();
+
+    let (face, s, t, major) = if abs.x >= abs.y && abs.x >= abs.z {
+        if dir.x > 0.0 { (0, -dir.z, -dir.y, abs.x) } else { (1, dir.z, -dir.y, abs.x) }
+    } else if abs.y >= abs.z {
+        if dir.y > 0.0 { (2, dir.x, dir.z, abs.y) } else { (3, dir.x, -dir.z, abs.y) }
+    } else if dir.z > 0.0 {
+        (4, dir.x, -dir.y, abs.z)
+    } else {
+        (5, -dir.x, -dir.y, abs.z)
+    };
+
+    (face, (s / major + 1.0) * 0.5, (t / major + 1.0) * 0.5)
+}
+
+impl Cubemap {
+    pub fn from_equirectangular(texture: &Texture, face_size: u32) -> Self {
+        Cubemap::from_fn(face_size, |dir| {
+            let u = dir.z.atan2(dir.x) / (2.0 * PI) + 0.5;
+            let v = dir.y.clamp(-1.0, 1.0).acos() / PI;
+            texture.sample_pixel(u, v)
+        })
+    }
+
+    // Builds a floating point cubemap by evaluating `f` for the direction through the center of each texel.
+    pub fn from_fn<F: Fn(Vec3) -> Vec4>(face_size: u32, f: F) -> Self {
+        Cubemap {
+            faces: std::array::from_fn(|face| Cubemap::render_face(face, face_size, 4, &f))
+        }
+    }
+
+    // Like `from_fn`, but stores a single channel, which is returned in red, green and blue.
+    pub fn from_fn_single_channel<F: Fn(Vec3) -> f32>(face_size: u32, f: F) -> Self {
+        Cubemap {
+            faces: std::array::from_fn(|face| Cubemap::render_face(face, face_size, 1, |dir| Vec4::splat(f(dir))))
+        }
+    }
+
+    // Calls `f` with the direction and solid angle of every texel of a cube with the given face size.
+    pub fn for_each_texel<F: FnMut(Vec3, f32)>(face_size: u32, mut f: F) {
+        for face in 0..6 {
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let u = (x as f32 + 0.5) / face_size as f32;
+                    let v = (y as f32 + 0.5) / face_size as f32;
+                    let s = u * 2.0 - 1.0;
+                    let t = v * 2.0 - 1.0;
+                    let solid_angle = 4.0 / ((face_size * face_size) as f32 * (1.0 + s * s + t * t).powf(1.5));
+
+                    f(face_direction(face, u, v), solid_angle);
+                }
+            }
+        }
+    }
+
+    pub fn face_size(&self) -> u32 {
+        self.faces[0].width()
+    }
+
+    // Halves the face size, averaging each 2x2 block of texels so detail finer than the new size is filtered out
+    // instead of aliasing.
+    pub fn downsample(&self) -> Cubemap {
+        let source_size = self.face_size();
+        let face_size = (source_size / 2).max(1);
+
+        Cubemap {
+            faces: std::array::from_fn(|face| {
+                let texel = |x: u32, y: u32| {
+                    let u = (x.min(source_size - 1) as f32 + 0.5) / source_size as f32;
+                    let v = (y.min(source_size - 1) as f32 + 0.5) / source_size as f32;
+                    self.faces[face].get_pixel(u, v)
+                };
+
+                let mut data = Vec::with_capacity((face_size * face_size) as usize * 4);
+                for y in 0..face_size {
+                    for x in 0..face_size {
+                        let (x, y) = (x * 2, y * 2);
+                        let color = (texel(x, y) + texel(x + 1, y) + texel(x, y + 1) + texel(x + 1, y + 1)) * 0.25;
+                        data.extend_from_slice(&color.to_array());
+                    }
+                }
+                Texture::new_f32(data, face_size, face_size, 4)
+            })
+        }
+    }
+
+    fn render_face<F: Fn(Vec3) -> Vec4>(face: usize, face_size: u32, channel_count: usize, f: F) -> Texture {
+        let mut data = Vec::with_capacity((face_size * face_size) as usize * channel_count);
+
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let u = (x as f32 + 0.5) / face_size as f32;
+                let v = (y as f32 + 0.5) / face_size as f32;
+                let color = f(face_direction(face, u, v));
+
+                data.extend_from_slice(&color.to_array()[..channel_count]);
+            }
+        }
+
+        Texture::new_f32(data, face_size, face_size, channel_count)
+    }
+
+    pub fn sample(&self, dir: &Vec3) -> Vec4 {
+        let (face, u, v) = direction_to_face(dir);
+        self.faces[face].sample_pixel(u, v)
+    }
+
+    // Nearest texel in the given direction, without filtering.
+    pub fn get_pixel(&self, dir: &Vec3) -> Vec4 {
+        let (face, u, v) = direction_to_face(dir);
+        self.faces[face].get_pixel(u, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsample_averages_checker() {
+        let face_size = 8;
+        let checker: Vec<f32> = (0..face_size * face_size)
+            .flat_map(|i| {
+                let value = ((i % face_size + i / face_size) % 2) as f32;
+                [value, value, value, 1.0]
+            })
+            .collect();
+        let cubemap = Cubemap {
+            faces: std::array::from_fn(|_| Texture::new_f32(checker.clone(), face_size as u32, face_size as u32, 4))
+        };
+
+        let mut level = cubemap;
+        for expected_size in [4, 2, 1] {
+            level = level.downsample();
+            assert_eq!(level.face_size(), expected_size);
+            Cubemap::for_each_texel(expected_size, |dir, _| {
+                assert_eq!(level.get_pixel(&dir), Vec4::new(0.5, 0.5, 0.5, 1.0));
+            });
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error
+    },
+    Gltf {
+        path: PathBuf,
+        source: gltf::Error
+    },
+    Unsupported {
+        path: PathBuf,
+        feature: String
+    },
+    MissingAttribute {
+        path: PathBuf,
+        mesh: usize,
+        primitive: usize,
+        attribute: &'static str
+    },
+    ImageDecode {
+        path: PathBuf,
+        reason: String
+    },
+    MissingScene {
+        path: PathBuf,
+        scene: usize
+    },
+    // Malformed contents of a text or binary model format, `line` is 0 for binary files.
+    Parse {
+        path: PathBuf,
+        line: usize,
+        reason: String
+    }
+}
+
+impl LoadError {
+    pub fn parse(path: &Path, line: usize, reason: String) -> Self {
+        LoadError::Parse {
+            path: path.to_path_buf(),
+            line,
+            reason
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, source } => {
+                write!(f, "failed to read '{}': {}", path.display(), source)
+            },
+            LoadError::Gltf { path, source } => {
+                write!(f, "failed to parse glTF '{}': {}", path.display(), source)
+            },
+            LoadError::Unsupported { path, feature } => {
+                write!(f, "'{}' uses an unsupported feature: {}", path.display(), feature)
+            },
+            LoadError::MissingAttribute { path, mesh, primitive, attribute } => {
+                write!(
+                    f,
+                    "'{}': mesh {} primitive {} is missing required attribute {}",
+                    path.display(), mesh, primitive, attribute
+                )
+            },
+            LoadError::ImageDecode { path, reason } => {
+                write!(f, "failed to decode image '{}': {}", path.display(), reason)
+            },
+            LoadError::MissingScene { path, scene } => {
+                write!(f, "'{}' has no scene {}", path.display(), scene)
+            },
+            LoadError::Parse { path, line: 0, reason } => {
+                write!(f, "failed to parse '{}': {}", path.display(), reason)
+            },
+            LoadError::Parse { path, line, reason } => {
+                write!(f, "failed to parse '{}' at line {}: {}", path.display(), line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Gltf { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
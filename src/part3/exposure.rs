@@ -0,0 +1,121 @@
+use glam::*;
+use crate::HdrFramebuffer;
+
+const HISTOGRAM_BINS: usize = 64;
+// Luminance that the average of the scene is exposed to, middle gray.
+const KEY_LUMINANCE: f32 = 0.18;
+
+fn luminance(color: Vec3) -> f32 {
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+// Histogram of log2 luminance over [min_ev, max_ev], values outside are clamped to the end bins.
+pub struct LuminanceHistogram {
+    bins: [u32; HISTOGRAM_BINS],
+    min_ev: f32,
+    max_ev: f32
+}
+
+impl LuminanceHistogram {
+    pub fn from_hdr(framebuffer: &HdrFramebuffer, min_ev: f32, max_ev: f32) -> Self {
+        let mut bins = [0; HISTOGRAM_BINS];
+        for pixel in framebuffer.pixels() {
+            let ev = luminance(pixel.xyz()).max(1e-10).log2();
+            let t = ((ev - min_ev) / (max_ev - min_ev)).clamp(0.0, 1.0);
+            bins[((t * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+        }
+
+        LuminanceHistogram {
+            bins,
+            min_ev,
+            max_ev
+        }
+    }
+
+    fn bin_ev(&self, bin: usize) -> f32 {
+        self.min_ev + (bin as f32 + 0.5) / HISTOGRAM_BINS as f32 * (self.max_ev - self.min_ev)
+    }
+
+    // Log2 luminance below which the given fraction of pixels lie.
+    pub fn percentile(&self, fraction: f32) -> f32 {
+        let total: u32 = self.bins.iter().sum();
+        let target = fraction.clamp(0.0, 1.0) * total as f32;
+
+        let mut count = 0.0;
+        for (bin, bin_count) in self.bins.iter().enumerate() {
+            count += *bin_count as f32;
+            if count >= target && *bin_count > 0 {
+                return self.bin_ev(bin);
+            }
+        }
+        self.max_ev
+    }
+
+    // Average log2 luminance of the pixels between the `low` and `high` percentiles, so that a few
+    // very dark or bright pixels don't pull the exposure around.
+    pub fn average(&self, low: f32, high: f32) -> f32 {
+        let total: u32 = self.bins.iter().sum();
+        let low = low.clamp(0.0, 1.0) * total as f32;
+        let high = high.clamp(0.0, 1.0) * total as f32;
+
+        let mut start = 0.0;
+        let mut sum = 0.0;
+        let mut weight = 0.0;
+        for (bin, bin_count) in self.bins.iter().enumerate() {
+            let end = start + *bin_count as f32;
+            let included = end.min(high) - start.max(low);
+            if included > 0.0 {
+                sum += self.bin_ev(bin) * included;
+                weight += included;
+            }
+            start = end;
+        }
+
+        if weight > 0.0 { sum / weight } else { self.percentile(0.5) }
+    }
+}
+
+// Eye adaptation: moves the exposure towards the one that maps the scene's average luminance to middle gray.
+pub struct AutoExposure {
+    // Range of scene luminance, in stops, that the exposure adapts to.
+    pub min_ev: f32,
+    pub max_ev: f32,
+    // Rate at which the exposure approaches its target, per second.
+    pub adaptation_speed: f32,
+    // Fractions of the darkest and brightest pixels ignored when averaging.
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    // Adapted scene luminance in stops, `None` until the first frame.
+    ev: Option<f32>
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        AutoExposure {
+            min_ev: -8.0,
+            max_ev: 8.0,
+            adaptation_speed: 2.0,
+            low_percentile: 0.5,
+            high_percentile: 0.95,
+            ev: None
+        }
+    }
+}
+
+impl AutoExposure {
+    // Adapts to the luminance of `framebuffer` over `delta_time` seconds and returns the exposure multiplier.
+    pub fn update(&mut self, framebuffer: &HdrFramebuffer, delta_time: f32) -> f32 {
+        let histogram = LuminanceHistogram::from_hdr(framebuffer, self.min_ev, self.max_ev);
+        let target = histogram
+            .average(self.low_percentile, self.high_percentile)
+            .clamp(self.min_ev, self.max_ev);
+
+        let ev = match self.ev {
+            Some(ev) => ev + (target - ev) * (1.0 - (-delta_time * self.adaptation_speed).exp()),
+            None => target
+        };
+        self.ev = Some(ev);
+
+        KEY_LUMINANCE / 2.0f32.powf(ev)
+    }
+}
@@ -0,0 +1,156 @@
+use glam::*;
+use crate::{Framebuffer, from_vec3_rgb};
+
+// Floating point render target holding unclamped linear radiance.
+pub struct HdrFramebuffer {
+    data: Vec<Vec4>,
+    width: usize,
+    height: usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    // Clamps to [0, 1], highlights clip hard.
+    Linear,
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve.
+    AcesFilmic,
+    // John Hable's filmic curve from Uncharted 2.
+    Uncharted2,
+    // Minimal AgX with the default contrast look, which already outputs display encoded values.
+    AgX
+}
+
+impl HdrFramebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        HdrFramebuffer {
+            data: vec![Vec4::ZERO; width * height],
+            width,
+            height
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: Vec4) {
+        self.data[y * self.width + x] = value;
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Vec4 {
+        self.data[y * self.width + x]
+    }
+
+    pub fn pixels(&self) -> &[Vec4] {
+        &self.data
+    }
+
+    pub fn clear(&mut self, value: Vec4) {
+        self.data.fill(value);
+    }
+
+    // Applies `exposure` (a multiplier) and the tone mapping operator, then writes the result to `target`.
+    pub fn resolve(&self, target: &mut Framebuffer, exposure: f32, tone_mapping: ToneMapping) {
+        for y in 0..self.height.min(target.height()) {
+            for x in 0..self.width.min(target.width()) {
+                let color = display_color(self.get_pixel(x, y).xyz(), exposure, tone_mapping);
+                target.set_pixel(x, y, from_vec3_rgb(&color));
+            }
+        }
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Tone maps linear `radiance` and encodes it with the sRGB transfer function the display expects.
+pub fn display_color(radiance: Vec3, exposure: f32, tone_mapping: ToneMapping) -> Vec3 {
+    let color = tone_map(radiance * exposure, tone_mapping).clamp(Vec3::ZERO, Vec3::ONE);
+    match tone_mapping {
+        ToneMapping::AgX => color,
+        _ => Vec3::new(linear_to_srgb(color.x), linear_to_srgb(color.y), linear_to_srgb(color.z))
+    }
+}
+
+fn uncharted2_curve(x: Vec3) -> Vec3 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    (x * (x * A + C * B) + D * E) / (x * (x * A + B) + D * F) - E / F
+}
+
+fn agx(color: Vec3) -> Vec3 {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let inset = Mat3::from_cols_array(&[
+        0.8424791, 0.04232824, 0.04237565,
+        0.0784336, 0.8784686, 0.0784336,
+        0.07922375, 0.07916613, 0.879143
+    ]);
+    let outset = Mat3::from_cols_array(&[
+        1.196879, -0.05289685, -0.05297164,
+        -0.09802088, 1.151903, -0.09804345,
+        -0.09902974, -0.09896118, 1.151074
+    ]);
+
+    let color = (inset * color).max(Vec3::splat(1e-10));
+    let x = ((Vec3::new(color.x.log2(), color.y.log2(), color.z.log2()) - MIN_EV) / (MAX_EV - MIN_EV))
+        .clamp(Vec3::ZERO, Vec3::ONE);
+
+    // Polynomial approximation of the default contrast curve.
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    let curve = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+
+    outset * curve
+}
+
+pub fn tone_map(color: Vec3, tone_mapping: ToneMapping) -> Vec3 {
+    let color = color.max(Vec3::ZERO);
+
+    match tone_mapping {
+        ToneMapping::Linear => color,
+        ToneMapping::Reinhard => color / (color + 1.0),
+        ToneMapping::AcesFilmic => {
+            (color * (color * 2.51 + 0.03)) / (color * (color * 2.43 + 0.59) + 0.14)
+        },
+        ToneMapping::Uncharted2 => {
+            const WHITE_POINT: f32 = 11.2;
+            const EXPOSURE_BIAS: f32 = 2.0;
+            uncharted2_curve(color * EXPOSURE_BIAS) / uncharted2_curve(Vec3::splat(WHITE_POINT))
+        },
+        ToneMapping::AgX => agx(color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_u8_rgb;
+
+    #[test]
+    fn encodes_srgb() {
+        let color = display_color(Vec3::splat(0.5), 1.0, ToneMapping::Linear);
+        assert_eq!(from_vec3_rgb(&color), from_u8_rgb(188, 188, 188));
+        assert_eq!(display_color(Vec3::ZERO, 1.0, ToneMapping::Reinhard), Vec3::ZERO);
+        assert_eq!(from_vec3_rgb(&display_color(Vec3::splat(2.0), 1.0, ToneMapping::Linear)), from_u8_rgb(255, 255, 255));
+
+        // AgX is display encoded already.
+        let radiance = Vec3::new(0.2, 0.5, 1.5);
+        assert_eq!(display_color(radiance, 1.0, ToneMapping::AgX), tone_map(radiance, ToneMapping::AgX).clamp(Vec3::ZERO, Vec3::ONE));
+    }
+}
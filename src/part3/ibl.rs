@@ -0,0 +1,248 @@
+use glam::*;
+use std::f32::consts::PI;
+use crate::Cubemap;
+use crate::shading::{Surface, fresnel_schlick};
+
+const IRRADIANCE_FACE_SIZE: u32 = 32;
+const PREFILTERED_FACE_SIZE: u32 = 128;
+const PREFILTERED_LEVELS: usize = 5;
+const PREFILTER_SAMPLE_COUNT: u32 = 64;
+const BRDF_LUT_SIZE: usize = 32;
+const BRDF_SAMPLE_COUNT: u32 = 128;
+
+// Precomputed image-based lighting derived from an (ideally HDR) environment cubemap.
+pub struct Environment {
+    // Spherical harmonics of the irradiance, already convolved with the cosine lobe and divided by pi.
+    irradiance: [Vec3; 9],
+    // Specular radiance prefiltered for roughness 0, 0.25, ..., 1.
+    prefiltered: Vec<Cubemap>,
+    // Scale and bias applied to F0 by the split sum approximation, indexed by (n_dot_v, roughness).
+    brdf_lut: Vec<Vec2>
+}
+
+fn radical_inverse(mut bits: u32) -> f32 {
+    bits = bits.rotate_right(16);
+    bits = ((bits & 0x55555555) << 1) | ((bits & 0xAAAAAAAA) >> 1);
+    bits = ((bits & 0x33333333) << 2) | ((bits & 0xCCCCCCCC) >> 2);
+    bits = ((bits & 0x0F0F0F0F) << 4) | ((bits & 0xF0F0F0F0) >> 4);
+    bits = ((bits & 0x00FF00FF) << 8) | ((bits & 0xFF00FF00) >> 8);
+    bits as f32 * 2.328_306_4e-10
+}
+
+fn hammersley(i: u32, count: u32) -> Vec2 {
+    Vec2::new(i as f32 / count as f32, radical_inverse(i))
+}
+
+fn importance_sample_ggx(xi: Vec2, n: Vec3, roughness: f32) -> Vec3 {
+    let a = roughness * roughness;
+
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let h = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+
+    let up = if n.z.abs() < 0.999 { Vec3::Z } else { Vec3::X };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(tangent);
+
+    (tangent * h.x + bitangent * h.y + n * h.z).normalize()
+}
+
+pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d).max(1e-7)
+}
+
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    g_v * g_l
+}
+
+fn sh_basis(n: Vec3) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * n.y,
+        0.488603 * n.z,
+        0.488603 * n.x,
+        1.092548 * n.x * n.y,
+        1.092548 * n.y * n.z,
+        0.315392 * (3.0 * n.z * n.z - 1.0),
+        1.092548 * n.x * n.z,
+        0.546274 * (n.x * n.x - n.y * n.y)
+    ]
+}
+
+fn sample_chain(chain: &[Cubemap], dir: &Vec3, lod: f32) -> Vec3 {
+    let level = (lod.round().max(0.0) as usize).min(chain.len() - 1);
+    chain[level].sample(dir).xyz()
+}
+
+fn prefilter(chain: &[Cubemap], r: Vec3, roughness: f32) -> Vec3 {
+    if roughness == 0.0 {
+        return chain[0].sample(&r).xyz();
+    }
+
+    let source_size = chain[0].face_size() as f32;
+    let texel_solid_angle = 4.0 * PI / (6.0 * source_size * source_size);
+
+    let mut color = Vec3::ZERO;
+    let mut weight = 0.0;
+    for i in 0..PREFILTER_SAMPLE_COUNT {
+        let h = importance_sample_ggx(hammersley(i, PREFILTER_SAMPLE_COUNT), r, roughness);
+        let l = 2.0 * r.dot(h) * h - r;
+
+        let n_dot_l = r.dot(l);
+        if n_dot_l > 0.0 {
+            // Sample a blurrier level for unlikely directions to avoid aliasing with few samples.
+            let n_dot_h = r.dot(h).max(0.0);
+            let pdf = distribution_ggx(n_dot_h, roughness) / 4.0 + 1e-4;
+            let sample_solid_angle = 1.0 / (PREFILTER_SAMPLE_COUNT as f32 * pdf);
+            let lod = 0.5 * (sample_solid_angle / texel_solid_angle).log2();
+
+            color += sample_chain(chain, &l, lod) * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+
+    color / weight.max(1e-4)
+}
+
+fn integrate_brdf(n_dot_v: f32, roughness: f32) -> Vec2 {
+    let v = Vec3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+
+    let mut scale = 0.0;
+    let mut bias = 0.0;
+    for i in 0..BRDF_SAMPLE_COUNT {
+        let h = importance_sample_ggx(hammersley(i, BRDF_SAMPLE_COUNT), Vec3::Z, roughness);
+        let l = 2.0 * v.dot(h) * h - v;
+
+        let n_dot_l = l.z.max(0.0);
+        let n_dot_h = h.z.max(0.0);
+        let v_dot_h = v.dot(h).max(0.0);
+
+        if n_dot_l > 0.0 {
+            let g = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v).max(1e-4);
+            let fc = (1.0 - v_dot_h).powi(5);
+
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+
+    Vec2::new(scale, bias) / BRDF_SAMPLE_COUNT as f32
+}
+
+impl Environment {
+    pub fn from_cubemap(cubemap: &Cubemap) -> Self {
+        let mut chain = vec![cubemap.clone()];
+        while chain.last().unwrap().face_size() > 1 {
+            let next = chain.last().unwrap().downsample();
+            chain.push(next);
+        }
+
+        let irradiance_source = chain
+            .iter()
+            .find(|level| level.face_size() <= IRRADIANCE_FACE_SIZE)
+            .unwrap();
+        let mut sh = [Vec3::ZERO; 9];
+        Cubemap::for_each_texel(irradiance_source.face_size(), |dir, solid_angle| {
+            let radiance = irradiance_source.sample(&dir).xyz();
+            for (coefficient, basis) in sh.iter_mut().zip(sh_basis(dir)) {
+                *coefficient += radiance * basis * solid_angle;
+            }
+        });
+
+        // Convolution with the clamped cosine lobe, divided by pi for lambertian reflectance.
+        let bands = [1.0, 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 0.25, 0.25, 0.25, 0.25, 0.25];
+        for (coefficient, band) in sh.iter_mut().zip(bands) {
+            *coefficient *= band;
+        }
+
+        let source_start = chain
+            .iter()
+            .position(|level| level.face_size() <= PREFILTERED_FACE_SIZE)
+            .unwrap();
+        let source = &chain[source_start..];
+        let prefiltered = (0..PREFILTERED_LEVELS)
+            .map(|level| {
+                let roughness = level as f32 / (PREFILTERED_LEVELS - 1) as f32;
+                let face_size = (source[0].face_size() >> level).max(1);
+                Cubemap::from_fn(face_size, |r| Vec4::from((prefilter(source, r, roughness), 1.0)))
+            })
+            .collect();
+
+        let mut brdf_lut = Vec::with_capacity(BRDF_LUT_SIZE * BRDF_LUT_SIZE);
+        for y in 0..BRDF_LUT_SIZE {
+            for x in 0..BRDF_LUT_SIZE {
+                let n_dot_v = ((x as f32 + 0.5) / BRDF_LUT_SIZE as f32).max(1e-3);
+                let roughness = (y as f32 + 0.5) / BRDF_LUT_SIZE as f32;
+                brdf_lut.push(integrate_brdf(n_dot_v, roughness));
+            }
+        }
+
+        Environment {
+            irradiance: sh,
+            prefiltered,
+            brdf_lut
+        }
+    }
+
+    // Diffuse irradiance around `n`, divided by pi.
+    pub fn irradiance(&self, n: &Vec3) -> Vec3 {
+        let radiance = self.irradiance
+            .iter()
+            .zip(sh_basis(*n))
+            .fold(Vec3::ZERO, |sum, (coefficient, basis)| sum + *coefficient * basis);
+        radiance.max(Vec3::ZERO)
+    }
+
+    pub fn prefiltered(&self, r: &Vec3, roughness: f32) -> Vec3 {
+        let level = roughness.clamp(0.0, 1.0) * (PREFILTERED_LEVELS - 1) as f32;
+        let lower = level.floor() as usize;
+        let upper = (lower + 1).min(PREFILTERED_LEVELS - 1);
+
+        let a = self.prefiltered[lower].sample(r).xyz();
+        let b = self.prefiltered[upper].sample(r).xyz();
+        a.lerp(b, level - lower as f32)
+    }
+
+    pub fn brdf(&self, n_dot_v: f32, roughness: f32) -> Vec2 {
+        let to_index = |v: f32| ((v.clamp(0.0, 1.0) * BRDF_LUT_SIZE as f32) as usize).min(BRDF_LUT_SIZE - 1);
+        self.brdf_lut[to_index(roughness) * BRDF_LUT_SIZE + to_index(n_dot_v)]
+    }
+
+    // Ambient lighting from the environment using the split sum approximation. Transmission is treated as
+    // thin walled, seeing the environment straight through the surface.
+    pub fn shade(&self, n: &Vec3, v: &Vec3, surface: &Surface) -> Vec3 {
+        let n_dot_v = n.dot(*v).max(1e-4);
+        let f0 = Vec3::splat(0.04).lerp(surface.base_color, surface.metallic);
+        let fresnel = f0 + (Vec3::splat(1.0 - surface.roughness).max(f0) - f0) * (1.0 - n_dot_v).powi(5);
+
+        let k_d = (Vec3::ONE - fresnel) * (1.0 - surface.metallic);
+        let diffuse = self.irradiance(n) * surface.base_color;
+        let transmitted = self.prefiltered(&-*v, surface.roughness) * surface.base_color;
+
+        let r = 2.0 * n.dot(*v) * *n - *v;
+        let brdf = self.brdf(n_dot_v, surface.roughness);
+        let specular = self.prefiltered(&r, surface.roughness) * (fresnel * brdf.x + brdf.y);
+
+        let color = k_d * diffuse.lerp(transmitted, surface.transmission) + specular;
+        if surface.clearcoat <= 0.0 {
+            return color;
+        }
+
+        let n = surface.clearcoat_normal;
+        let n_dot_v = n.dot(*v).max(1e-4);
+        let clearcoat_fresnel = fresnel_schlick(Vec3::splat(0.04), n_dot_v).x;
+        let r = 2.0 * n.dot(*v) * n - *v;
+        let brdf = self.brdf(n_dot_v, surface.clearcoat_roughness);
+        let clearcoat = self.prefiltered(&r, surface.clearcoat_roughness) * (0.04 * brdf.x + brdf.y);
+
+        color * (1.0 - surface.clearcoat * clearcoat_fresnel) + clearcoat * surface.clearcoat
+    }
+}
@@ -0,0 +1,230 @@
+use std::path::Path;
+use crate::{Texture, ColorSpace, LoadError};
+use crate::bcn::{BlockFormat, BlockPixels, decode_blocks};
+
+const IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+#[derive(Clone, Copy)]
+enum PixelFormat {
+    Rgb8,
+    Rgba8,
+    Compressed(BlockFormat)
+}
+
+fn pixel_format(vk_format: u32) -> Option<PixelFormat> {
+    match vk_format {
+        23 | 29 => Some(PixelFormat::Rgb8),
+        37 | 43 => Some(PixelFormat::Rgba8),
+        131 | 132 => Some(PixelFormat::Compressed(BlockFormat::Bc1)),
+        133 | 134 => Some(PixelFormat::Compressed(BlockFormat::Bc1Alpha)),
+        135 | 136 => Some(PixelFormat::Compressed(BlockFormat::Bc2)),
+        137 | 138 => Some(PixelFormat::Compressed(BlockFormat::Bc3)),
+        139 => Some(PixelFormat::Compressed(BlockFormat::Bc4)),
+        140 => Some(PixelFormat::Compressed(BlockFormat::Bc4Signed)),
+        141 => Some(PixelFormat::Compressed(BlockFormat::Bc5)),
+        142 => Some(PixelFormat::Compressed(BlockFormat::Bc5Signed)),
+        143 => Some(PixelFormat::Compressed(BlockFormat::Bc6h)),
+        144 => Some(PixelFormat::Compressed(BlockFormat::Bc6hSigned)),
+        145 | 146 => Some(PixelFormat::Compressed(BlockFormat::Bc7)),
+        _ => None
+    }
+}
+
+// The _SRGB variants of the formats above.
+fn color_space(vk_format: u32) -> ColorSpace {
+    match vk_format {
+        29 | 43 | 132 | 134 | 136 | 138 | 146 => ColorSpace::Srgb,
+        _ => ColorSpace::Linear
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(&IDENTIFIER)
+}
+
+pub fn load_ktx2(bytes: &[u8], file_path: &Path) -> Result<Texture, LoadError> {
+    let malformed = |reason: &str| LoadError::ImageDecode {
+        path: file_path.to_path_buf(),
+        reason: reason.to_string()
+    };
+    let unsupported = |feature: String| LoadError::Unsupported {
+        path: file_path.to_path_buf(),
+        feature
+    };
+
+    if bytes.len() < HEADER_SIZE || bytes[0..12] != IDENTIFIER {
+        return Err(malformed("not a KTX2 file"));
+    }
+
+    let vk_format = read_u32(bytes, 12);
+    let width = read_u32(bytes, 20) as usize;
+    let height = read_u32(bytes, 24) as usize;
+    let depth = read_u32(bytes, 28);
+    let layer_count = read_u32(bytes, 32);
+    let face_count = read_u32(bytes, 36);
+    let level_count = read_u32(bytes, 40).max(1) as usize;
+    let supercompression = read_u32(bytes, 44);
+
+    let format = pixel_format(vk_format)
+        .ok_or_else(|| unsupported(format!("KTX2 vkFormat {}", vk_format)))?;
+    if supercompression != 0 {
+        return Err(unsupported(format!("KTX2 supercompression scheme {}", supercompression)));
+    }
+    if depth > 1 || layer_count > 1 || face_count != 1 {
+        return Err(unsupported("KTX2 array, cubemap or 3D textures".to_string()));
+    }
+    if width == 0 || height == 0 {
+        return Err(malformed("zero sized image"));
+    }
+    // Each level halves the size down to 1x1, further levels would shift the size out of range.
+    let max_level_count = (usize::BITS - width.max(height).leading_zeros()) as usize;
+    if level_count > max_level_count {
+        return Err(malformed("more mip levels than the image size allows"));
+    }
+    if bytes.len() < HEADER_SIZE + level_count * LEVEL_INDEX_ENTRY_SIZE {
+        return Err(malformed("truncated level index"));
+    }
+
+    let mut levels = Vec::with_capacity(level_count);
+    for level in 0..level_count {
+        let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+        let offset = read_u64(bytes, entry) as usize;
+        let length = read_u64(bytes, entry + 8) as usize;
+        let data = offset.checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| malformed("level data out of bounds"))?;
+
+        let level_width = (width >> level).max(1);
+        let level_height = (height >> level).max(1);
+
+        let truncated = || malformed("truncated level data");
+        // Sizes are checked so huge dimensions in a small file are reported instead of overflowing.
+        let level_size = |texel_count: usize, texel_size: usize| {
+            texel_count.checked_mul(texel_size).filter(|size| *size <= data.len()).ok_or_else(truncated)
+        };
+        let (pixels, channel_count) = match format {
+            PixelFormat::Rgb8 => {
+                let size = level_size(level_width.saturating_mul(level_height), 3)?;
+                (BlockPixels::U8(data[..size].to_vec()), 3)
+            },
+            PixelFormat::Rgba8 => {
+                let size = level_size(level_width.saturating_mul(level_height), 4)?;
+                (BlockPixels::U8(data[..size].to_vec()), 4)
+            },
+            PixelFormat::Compressed(block_format) => {
+                level_size(level_width.div_ceil(4).saturating_mul(level_height.div_ceil(4)), block_format.block_size())?;
+                (decode_blocks(block_format, data, level_width, level_height), 4)
+            }
+        };
+
+        let (level_width, level_height) = (level_width as u32, level_height as u32);
+        levels.push(match pixels {
+            BlockPixels::U8(pixels) => Texture::new(pixels, level_width, level_height, channel_count),
+            BlockPixels::F32(pixels) => Texture::new_f32(pixels, level_width, level_height, channel_count)
+        });
+    }
+
+    let mut levels = levels.into_iter();
+    let base = levels.next().unwrap();
+    Ok(base.with_mips(levels.collect()).with_default_color_space(color_space(vk_format)))
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+    use super::*;
+
+    // A 2D image header followed by a level index with the given offset and length for every level.
+    fn ktx2(vk_format: u32, width: u32, height: u32, levels: &[(u64, u64)]) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        for (offset, value) in [(12, vk_format), (20, width), (24, height), (36, 1), (40, levels.len() as u32)] {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        for &(offset, length) in levels {
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(length.to_le_bytes());
+            bytes.extend(length.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn error_reason(bytes: &[u8]) -> String {
+        match load_ktx2(bytes, Path::new("test.ktx2")) {
+            Err(LoadError::ImageDecode { reason, .. }) => reason,
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("malformed file loaded")
+        }
+    }
+
+    #[test]
+    fn loads_rgba8_levels() {
+        let data_offset = (HEADER_SIZE + 2 * LEVEL_INDEX_ENTRY_SIZE) as u64;
+        let mut bytes = ktx2(37, 2, 1, &[(data_offset, 8), (data_offset + 8, 4)]);
+        bytes.extend([255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255]);
+
+        let texture = load_ktx2(&bytes, Path::new("test.ktx2")).unwrap();
+        assert_eq!(texture.width(), 2);
+        assert_eq!(texture.get_pixel(0.75, 0.5) * 255.99, Vec4::new(0.0, 255.0, 0.0, 255.0));
+        // The 1x1 level is only blue.
+        assert_eq!(texture.sample_pixel_lod(0.5, 0.5, 1.0) * 255.99, Vec4::new(0.0, 0.0, 255.0, 255.0));
+    }
+
+    #[test]
+    fn keeps_srgb_format() {
+        let data_offset = (HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE) as u64;
+        let load = |vk_format: u32| {
+            let mut bytes = ktx2(vk_format, 1, 1, &[(data_offset, 4)]);
+            bytes.extend([188, 188, 188, 128]);
+            load_ktx2(&bytes, Path::new("test.ktx2")).unwrap()
+        };
+
+        // sRGB 188 is about 0.5 linear, alpha is never converted. The file wins over how the texture is used.
+        let texel = load(43).with_default_color_space(ColorSpace::Linear).get_pixel(0.5, 0.5);
+        assert!((texel.x - 0.5029).abs() < 1e-3);
+        assert_eq!(texel.w * 255.99, 128.0);
+
+        let texel = load(37).with_default_color_space(ColorSpace::Srgb).get_pixel(0.5, 0.5);
+        assert_eq!(texel.x * 255.99, 188.0);
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let bytes = ktx2(37, 1, 1, &[]);
+        assert!(is_ktx2(&bytes[..40]));
+        assert_eq!(error_reason(&bytes[..40]), "not a KTX2 file");
+    }
+
+    #[test]
+    fn rejects_truncated_level_index() {
+        let bytes = ktx2(37, 4, 4, &[(0, 0), (0, 0)]);
+        assert_eq!(error_reason(&bytes[..HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE]), "truncated level index");
+    }
+
+    #[test]
+    fn rejects_level_out_of_bounds() {
+        let mut bytes = ktx2(37, 1, 1, &[(104, 8)]);
+        bytes.extend([0; 4]);
+        assert_eq!(error_reason(&bytes), "level data out of bounds");
+
+        // An offset and length that overflow when added.
+        let bytes = ktx2(37, 1, 1, &[(u64::MAX, 2)]);
+        assert_eq!(error_reason(&bytes), "level data out of bounds");
+    }
+
+    #[test]
+    fn rejects_too_many_levels() {
+        let bytes = ktx2(37, 4, 4, &[(0, 0); 4]);
+        assert_eq!(error_reason(&bytes), "more mip levels than the image size allows");
+    }
+}
@@ -0,0 +1,97 @@
+use glam::*;
+use crate::ShadowSettings;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    // Cone angles are in radians, measured from the light direction.
+    Spot { inner_cone_angle: f32, outer_cone_angle: f32 }
+}
+
+// A punctual light as described by KHR_lights_punctual. Intensity is in lux for directional
+// lights and candela for point and spot lights.
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    // Distance at which point and spot lights are cut off, unlimited if `None`.
+    pub range: Option<f32>,
+    pub position: Vec3,
+    // Direction the light travels in, ignored by point lights.
+    pub direction: Vec3,
+    // Shadow map parameters, the light casts no shadows if `None`.
+    pub shadow: Option<ShadowSettings>
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light {
+            kind: LightKind::Directional,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+            position: Vec3::ZERO,
+            direction: Vec3::NEG_Z,
+            shadow: None
+        }
+    }
+}
+
+impl Light {
+    // Creates a light from a glTF light attached to a node with the given transform.
+    pub fn from_gltf(light: &gltf::khr_lights_punctual::Light, transform: &Mat4) -> Self {
+        let kind = match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+            gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+            gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => {
+                LightKind::Spot { inner_cone_angle, outer_cone_angle }
+            }
+        };
+
+        Light {
+            kind,
+            color: Vec3::from(light.color()),
+            intensity: light.intensity(),
+            range: light.range(),
+            ..Default::default()
+        }.transformed(transform)
+    }
+
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Light {
+            position: transform.transform_point3(self.position),
+            direction: transform.transform_vector3(self.direction).normalize_or_zero(),
+            ..*self
+        }
+    }
+
+    // Returns the direction towards the light and the radiance arriving at `position`.
+    pub fn incident(&self, position: &Vec3) -> (Vec3, Vec3) {
+        let radiance = self.color * self.intensity;
+
+        if self.kind == LightKind::Directional {
+            return (-self.direction, radiance);
+        }
+
+        let to_light = self.position - *position;
+        let distance_squared = to_light.length_squared().max(1e-8);
+        let l = to_light / distance_squared.sqrt();
+
+        // Inverse square falloff, smoothly windowed to zero at the range as recommended by the extension.
+        let mut attenuation = 1.0 / distance_squared;
+        if let Some(range) = self.range {
+            attenuation *= (1.0 - (distance_squared / (range * range)).powi(2)).clamp(0.0, 1.0);
+        }
+
+        if let LightKind::Spot { inner_cone_angle, outer_cone_angle } = self.kind {
+            let cos_outer = outer_cone_angle.cos();
+            let scale = 1.0 / (inner_cone_angle.cos() - cos_outer).max(1e-3);
+            let spot = ((self.direction.dot(-l) - cos_outer) * scale).clamp(0.0, 1.0);
+            attenuation *= spot * spot;
+        }
+
+        (l, radiance * attenuation)
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::Path;
 use std::time::SystemTime;
 
 use glam::*;
@@ -5,9 +6,51 @@ use glam::*;
 mod window;
 use window::{Window, Framebuffer};
 mod model;
-use model::{Model, Vertex, Material, load_model};
+use model::{Model, Mesh, MeshInstance, Vertex, Material, MaterialTexture, MorphTarget, load_model_with_cache};
 mod texture;
-use texture::{Texture, load_texture};
+use texture::{Texture, TextureCache, TextureKey, TextureLayout, ColorSpace, load_texture, load_texture_from_memory};
+mod error;
+use error::LoadError;
+mod bcn;
+mod ktx2;
+mod cubemap;
+use cubemap::{Cubemap, load_cubemap, load_equirectangular_cubemap};
+mod ibl;
+use ibl::Environment;
+mod benchmark;
+use benchmark::run_texture_layout_benchmark;
+mod shading;
+use shading::{ShadingModel, Surface, cook_torrance};
+mod tangent;
+use tangent::generate_tangents;
+mod light;
+use light::{Light, LightKind};
+mod shadow;
+use shadow::{ShadowMap, ShadowSettings, ShadowFilter, LightShadow, bounding_sphere};
+mod cascade;
+use cascade::CascadedShadowMap;
+mod cube_shadow;
+use cube_shadow::CubeShadowMap;
+mod hdr;
+use hdr::{HdrFramebuffer, ToneMapping};
+mod exposure;
+use exposure::AutoExposure;
+mod skinning;
+use skinning::{SkinningMethod, skin_vertices};
+mod morph;
+use morph::morph_vertices;
+mod camera;
+use camera::{Camera, Projection};
+mod animation;
+use animation::{AnimationClip, AnimationPlayer, Channel, ChannelValues, Interpolation};
+mod obj;
+use obj::{load_obj_with_cache, triangulate, default_material};
+mod normals;
+use normals::{NormalGeneration, generate_normals, triangle_normal, position_key};
+mod stl;
+use stl::load_stl;
+mod ply;
+use ply::load_ply;
 
 fn from_u8_rgb(r: u8, g: u8, b: u8) -> u32 {
     let (r, g, b) = (r as u32, g as u32, b as u32);
@@ -22,23 +65,52 @@ fn edge_function(a: &Vec2, c: &Vec2, b: &Vec2) -> f32 {
     (c.x - a.x) * (b.y - a.y) - (c.y - a.y) * (b.x - a.x)
 }
 
+const CASCADE_COLORS: [Vec3; 4] = [
+    Vec3::new(1.0, 0.3, 0.3),
+    Vec3::new(0.3, 1.0, 0.3),
+    Vec3::new(0.3, 0.3, 1.0),
+    Vec3::new(1.0, 1.0, 0.3)
+];
+
+struct Uniforms<'a> {
+    mvp: Mat4,
+    model_matrix: Mat4,
+    inv_trans_model_matrix: Mat4,
+    camera_position: Vec3,
+    environment: Option<&'a Environment>,
+    shading_model: ShadingModel,
+    // Lights in world space.
+    lights: &'a [Light],
+    // Shadow maps of each light in `lights`, if it casts shadows.
+    shadow_maps: &'a [Option<LightShadow>],
+    // Tints surfaces by the shadow cascade they use.
+    debug_cascades: bool,
+    // Light reaching every surface regardless of lights, scaled by occlusion.
+    ambient: Vec3
+}
+
 fn draw_triangle(
-    framebuffer: &mut Framebuffer,
+    framebuffer: &mut HdrFramebuffer,
     depth_buffer: &mut Framebuffer,
     v0: &Vertex, v1: &Vertex, v2: &Vertex,
-    mvp: &Mat4,
-    inv_trans_model_matrix: &Mat4,
+    uniforms: &Uniforms,
     material: &Material
 ) {
-    let v0_clip_space = project(&v0.position, mvp);
-    let v1_clip_space = project(&v1.position, mvp);
-    let v2_clip_space = project(&v2.position, mvp);
+    let v0_clip_space = project(&v0.position, &uniforms.mvp);
+    let v1_clip_space = project(&v1.position, &uniforms.mvp);
+    let v2_clip_space = project(&v2.position, &uniforms.mvp);
 
     let screen_size = Vec2::new(framebuffer.width() as f32, framebuffer.height() as f32);
     let v0_screen_space = clip_to_screen_space(&v0_clip_space.0.xy(), &screen_size);
     let v1_screen_space = clip_to_screen_space(&v1_clip_space.0.xy(), &screen_size);
     let v2_screen_space = clip_to_screen_space(&v2_clip_space.0.xy(), &screen_size);
 
+    let screen_area = edge_function(&v0_screen_space, &v1_screen_space, &v2_screen_space).abs();
+    let uv_areas = [
+        (v1.tex_coord - v0.tex_coord).perp_dot(v2.tex_coord - v0.tex_coord).abs(),
+        (v1.tex_coord_1 - v0.tex_coord_1).perp_dot(v2.tex_coord_1 - v0.tex_coord_1).abs()
+    ];
+
     let min = v0_screen_space.min(v1_screen_space.min(v2_screen_space)).max(Vec2::ZERO);
     let max = (v0_screen_space.max(v1_screen_space.max(v2_screen_space)) + 1.0).min(screen_size);
 
@@ -66,10 +138,10 @@ fn draw_triangle(
                 if z < depth {
                     depth_buffer.set_pixel_f32(x, y, z);
 
-                    let n0 = *inv_trans_model_matrix * Vec4::from((v0.normal, 1.0));
-                    let n1 = *inv_trans_model_matrix * Vec4::from((v1.normal, 1.0));
-                    let n2 = *inv_trans_model_matrix * Vec4::from((v2.normal, 1.0));
-                    let normal = ((n0 * v0_clip_space.1 * bary_coords.x
+                    let n0 = uniforms.inv_trans_model_matrix * Vec4::from((v0.normal, 1.0));
+                    let n1 = uniforms.inv_trans_model_matrix * Vec4::from((v1.normal, 1.0));
+                    let n2 = uniforms.inv_trans_model_matrix * Vec4::from((v2.normal, 1.0));
+                    let geometric_normal = ((n0 * v0_clip_space.1 * bary_coords.x
                                         + n1 * v1_clip_space.1 * bary_coords.y
                                         + n2 * v2_clip_space.1 * bary_coords.z).xyz()
                                             * correction).normalize();
@@ -77,18 +149,215 @@ fn draw_triangle(
                     let tex_coord = (v0.tex_coord * v0_clip_space.1 * bary_coords.x
                                             + v1.tex_coord * v1_clip_space.1 * bary_coords.y
                                             + v2.tex_coord * v2_clip_space.1 * bary_coords.z) * correction;
+                    let tex_coord_1 = (v0.tex_coord_1 * v0_clip_space.1 * bary_coords.x
+                                            + v1.tex_coord_1 * v1_clip_space.1 * bary_coords.y
+                                            + v2.tex_coord_1 * v2_clip_space.1 * bary_coords.z) * correction;
+                    let tex_coords = [tex_coord, tex_coord_1];
+
+                    let color = (v0.color * v0_clip_space.1 * bary_coords.x
+                                    + v1.color * v1_clip_space.1 * bary_coords.y
+                                    + v2.color * v2_clip_space.1 * bary_coords.z) * correction;
 
-                    let mut base_color = material.base_color;
+                    let mut base_color = material.base_color * color;
                     if let Some(base_color_texture) = &material.base_color_texture {
-                        base_color *= base_color_texture.sample_pixel(tex_coord.x, tex_coord.y);
+                        base_color *= base_color_texture.sample(&tex_coords, &uv_areas, screen_area);
+                    }
+
+                    if material.unlit {
+                        framebuffer.set_pixel(x, y, Vec4::from((base_color.xyz(), 1.0)));
+                        continue;
+                    }
+
+                    // Bends the geometric normal by a tangent space normal map.
+                    let perturb_normal = |normal_texture: &MaterialTexture, scale: f32| {
+                        let t0 = uniforms.model_matrix.transform_vector3(v0.tangent.xyz());
+                        let t1 = uniforms.model_matrix.transform_vector3(v1.tangent.xyz());
+                        let t2 = uniforms.model_matrix.transform_vector3(v2.tangent.xyz());
+                        let tangent = (t0 * v0_clip_space.1 * bary_coords.x
+                                        + t1 * v1_clip_space.1 * bary_coords.y
+                                        + t2 * v2_clip_space.1 * bary_coords.z) * correction;
+                        let tangent = (tangent - geometric_normal * geometric_normal.dot(tangent)).normalize_or_zero();
+                        let bitangent = geometric_normal.cross(tangent) * v0.tangent.w;
+
+                        let sample = normal_texture.sample(&tex_coords, &uv_areas, screen_area).xyz() * 2.0 - 1.0;
+                        let tangent_space_normal = sample * Vec3::new(scale, scale, 1.0);
+
+                        (tangent * tangent_space_normal.x
+                            + bitangent * tangent_space_normal.y
+                            + geometric_normal * tangent_space_normal.z).normalize_or_zero()
+                    };
+
+                    let normal = match &material.normal_texture {
+                        Some(normal_texture) => perturb_normal(normal_texture, material.normal_scale),
+                        None => geometric_normal
+                    };
+
+                    let mut metallic = material.metallic;
+                    let mut roughness = material.roughness;
+                    if let Some(metallic_roughness_texture) = &material.metallic_roughness_texture {
+                        let sample = metallic_roughness_texture.sample(&tex_coords, &uv_areas, screen_area);
+                        roughness *= sample.y;
+                        metallic *= sample.z;
+                    }
+
+                    let mut surface = Surface {
+                        base_color: base_color.xyz(),
+                        metallic,
+                        roughness,
+                        transmission: material.transmission,
+                        clearcoat: material.clearcoat,
+                        clearcoat_roughness: material.clearcoat_roughness,
+                        clearcoat_normal: geometric_normal,
+                        sheen_color: material.sheen_color,
+                        sheen_roughness: material.sheen_roughness
+                    };
+                    if let Some(transmission_texture) = &material.transmission_texture {
+                        surface.transmission *= transmission_texture.sample(&tex_coords, &uv_areas, screen_area).x;
+                    }
+                    if let Some(clearcoat_texture) = &material.clearcoat_texture {
+                        surface.clearcoat *= clearcoat_texture.sample(&tex_coords, &uv_areas, screen_area).x;
+                    }
+                    if let Some(clearcoat_roughness_texture) = &material.clearcoat_roughness_texture {
+                        surface.clearcoat_roughness *= clearcoat_roughness_texture.sample(&tex_coords, &uv_areas, screen_area).y;
+                    }
+                    if let Some(clearcoat_normal_texture) = &material.clearcoat_normal_texture {
+                        surface.clearcoat_normal = perturb_normal(clearcoat_normal_texture, material.clearcoat_normal_scale);
+                    }
+                    if let Some(sheen_color_texture) = &material.sheen_color_texture {
+                        surface.sheen_color *= sheen_color_texture.sample(&tex_coords, &uv_areas, screen_area).xyz();
+                    }
+                    if let Some(sheen_roughness_texture) = &material.sheen_roughness_texture {
+                        surface.sheen_roughness *= sheen_roughness_texture.sample(&tex_coords, &uv_areas, screen_area).w;
+                    }
+
+                    let p0 = uniforms.model_matrix.transform_point3(v0.position);
+                    let p1 = uniforms.model_matrix.transform_point3(v1.position);
+                    let p2 = uniforms.model_matrix.transform_point3(v2.position);
+                    let world_position = (p0 * v0_clip_space.1 * bary_coords.x
+                                            + p1 * v1_clip_space.1 * bary_coords.y
+                                            + p2 * v2_clip_space.1 * bary_coords.z) * correction;
+                    let view_dir = (uniforms.camera_position - world_position).normalize();
+
+                    let mut occlusion = 1.0;
+                    if let Some(occlusion_texture) = &material.occlusion_texture {
+                        let sample = occlusion_texture.sample(&tex_coords, &uv_areas, screen_area).x;
+                        occlusion = 1.0 + material.occlusion_strength * (sample - 1.0);
+                    }
+
+                    let mut final_color = uniforms.ambient * surface.base_color * occlusion;
+
+                    // The lambertian model only has the diffuse lobe, without any of the layers.
+                    let lambertian = Surface {
+                        metallic: 0.0,
+                        roughness: 1.0,
+                        clearcoat: 0.0,
+                        sheen_color: Vec3::ZERO,
+                        ..surface
+                    };
+
+                    for (light, shadow_map) in uniforms.lights.iter().zip(uniforms.shadow_maps) {
+                        let (light_dir, mut radiance) = light.incident(&world_position);
+                        if let Some(shadow_map) = shadow_map {
+                            radiance *= shadow_map.visibility(&world_position);
+                        }
+                        final_color += match uniforms.shading_model {
+                            ShadingModel::Lambert => {
+                                let light_intensity = normal.dot(light_dir).max(0.0);
+                                lambertian.base_color * (1.0 - lambertian.transmission) / std::f32::consts::PI * radiance * light_intensity
+                            },
+                            ShadingModel::CookTorrance => {
+                                cook_torrance(&normal, &view_dir, &light_dir, &radiance, &surface)
+                            }
+                        };
+                    }
+
+                    if let Some(environment) = uniforms.environment {
+                        final_color += occlusion * match uniforms.shading_model {
+                            ShadingModel::Lambert => environment.shade(&normal, &view_dir, &lambertian),
+                            ShadingModel::CookTorrance => environment.shade(&normal, &view_dir, &surface)
+                        };
                     }
 
-                    let light_dir = Vec3::new(0.3, -0.8, -0.4).normalize();
-                    let light_intensity = normal.dot(-light_dir);
+                    if uniforms.debug_cascades {
+                        let cascade = uniforms.shadow_maps
+                            .iter()
+                            .flatten()
+                            .find_map(|shadow_map| shadow_map.cascade(&world_position));
+                        if let Some(cascade) = cascade {
+                            final_color *= CASCADE_COLORS[cascade % CASCADE_COLORS.len()];
+                        }
+                    }
 
-                    let final_color = base_color * light_intensity;
+                    let mut emissive = material.emissive * material.emissive_strength;
+                    if let Some(emissive_texture) = &material.emissive_texture {
+                        emissive *= emissive_texture.sample(&tex_coords, &uv_areas, screen_area).xyz();
+                    }
+                    final_color += emissive;
 
-                    framebuffer.set_pixel(x, y, from_vec3_rgb(&final_color.xyz()));
+                    framebuffer.set_pixel(x, y, Vec4::from((final_color, 1.0)));
+                }
+            }
+        }
+    }
+}
+
+// Rasterizes only the depth of a triangle, offset by a constant and a slope-scaled bias.
+// Both windings are drawn so that open meshes still write depth.
+fn draw_triangle_depth(
+    depth_buffer: &mut Framebuffer,
+    v0: &Vec3, v1: &Vec3, v2: &Vec3,
+    mvp: &Mat4,
+    depth_bias: f32,
+    slope_bias: f32
+) {
+    let v0_clip_space = project(v0, mvp);
+    let v1_clip_space = project(v1, mvp);
+    let v2_clip_space = project(v2, mvp);
+    if v0_clip_space.1 <= 0.0 || v1_clip_space.1 <= 0.0 || v2_clip_space.1 <= 0.0 {
+        return;
+    }
+
+    let screen_size = Vec2::new(depth_buffer.width() as f32, depth_buffer.height() as f32);
+    let v0_screen_space = clip_to_screen_space(&v0_clip_space.0.xy(), &screen_size);
+    let v1_screen_space = clip_to_screen_space(&v1_clip_space.0.xy(), &screen_size);
+    let v2_screen_space = clip_to_screen_space(&v2_clip_space.0.xy(), &screen_size);
+
+    let area = edge_function(&v0_screen_space, &v1_screen_space, &v2_screen_space);
+    if area == 0.0 {
+        return;
+    }
+
+    // Gradient of the depth plane across the screen.
+    let e1 = v1_screen_space - v0_screen_space;
+    let e2 = v2_screen_space - v0_screen_space;
+    let dz1 = v1_clip_space.0.z - v0_clip_space.0.z;
+    let dz2 = v2_clip_space.0.z - v0_clip_space.0.z;
+    let det = e1.perp_dot(e2);
+    let dz_dx = (dz1 * e2.y - dz2 * e1.y) / det;
+    let dz_dy = (dz2 * e1.x - dz1 * e2.x) / det;
+    let bias = depth_bias + slope_bias * dz_dx.abs().max(dz_dy.abs());
+
+    let min = v0_screen_space.min(v1_screen_space.min(v2_screen_space)).max(Vec2::ZERO);
+    let max = (v0_screen_space.max(v1_screen_space.max(v2_screen_space)) + 1.0).min(screen_size);
+
+    for x in (min.x as usize)..(max.x as usize) {
+        for y in (min.y as usize)..(max.y as usize) {
+            let p = Vec2::new(x as f32, y as f32) + 0.5;
+
+            let a0 = edge_function(&v1_screen_space, &v2_screen_space, &p);
+            let a1 = edge_function(&v2_screen_space, &v0_screen_space, &p);
+            let a2 = edge_function(&v0_screen_space, &v1_screen_space, &p);
+            let overlaps = (a0 >= 0.0 && a1 >= 0.0 && a2 >= 0.0) || (a0 <= 0.0 && a1 <= 0.0 && a2 <= 0.0);
+
+            if overlaps {
+                let bary_coords = Vec3::new(a0, a1, a2) / area;
+                let z = v0_clip_space.0.z * bary_coords.x
+                        + v1_clip_space.0.z * bary_coords.y
+                        + v2_clip_space.0.z * bary_coords.z
+                        + bias;
+
+                if z < depth_buffer.get_pixel_f32(x, y) {
+                    depth_buffer.set_pixel_f32(x, y, z.clamp(0.0, 1.0));
                 }
             }
         }
@@ -106,18 +375,190 @@ fn clip_to_screen_space(clip_space: &Vec2, screen_size: &Vec2) -> Vec2 {
     (*clip_space * -0.5 + 0.5) * *screen_size
 }
 
+fn screen_to_clip_space(screen_space: &Vec2, screen_size: &Vec2) -> Vec2 {
+    (*screen_space / *screen_size - 0.5) / -0.5
+}
+
+fn draw_skybox(
+    framebuffer: &mut HdrFramebuffer,
+    depth_buffer: &mut Framebuffer,
+    skybox: &Cubemap,
+    inv_view_proj: &Mat4
+) {
+    let screen_size = Vec2::new(framebuffer.width() as f32, framebuffer.height() as f32);
+
+    for x in 0..framebuffer.width() {
+        for y in 0..framebuffer.height() {
+            if depth_buffer.get_pixel_f32(x, y) < 1.0 {
+                continue;
+            }
+
+            let p = Vec2::new(x as f32, y as f32) + 0.5;
+            let clip_space = screen_to_clip_space(&p, &screen_size);
+            // Any two depths along the pixel give its view direction, the far plane may be at infinity.
+            let near = inv_view_proj.project_point3(Vec3::from((clip_space, 0.0)));
+            let far = inv_view_proj.project_point3(Vec3::from((clip_space, 0.5)));
+
+            let color = skybox.sample(&(far - near).normalize());
+            framebuffer.set_pixel(x, y, Vec4::from((color.xyz(), 1.0)));
+        }
+    }
+}
+
+fn load_skybox(args: &[String]) -> Result<Option<Cubemap>, LoadError> {
+    if let Some(i) = args.iter().position(|arg| arg == "--skybox") {
+        if let Some(file_path) = args.get(i + 1) {
+            return load_equirectangular_cubemap(file_path).map(Some);
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--skybox-faces") {
+        if let Some(face_paths) = args.get(i + 1..i + 7) {
+            let face_paths: &[String; 6] = face_paths.try_into().unwrap();
+            return load_cubemap(face_paths).map(Some);
+        }
+    }
+
+    Ok(None)
+}
+
+fn load_environment(args: &[String]) -> Result<Option<Cubemap>, LoadError> {
+    if let Some(i) = args.iter().position(|arg| arg == "--environment") {
+        if let Some(file_path) = args.get(i + 1) {
+            return load_equirectangular_cubemap(file_path).map(Some);
+        }
+    }
+
+    Ok(None)
+}
+
+fn parse_texture_layout(args: &[String]) -> TextureLayout {
+    let Some(i) = args.iter().position(|arg| arg == "--texture-layout") else {
+        return TextureLayout::Linear;
+    };
+
+    match args.get(i + 1).map(String::as_str) {
+        Some("tiled4x4") => TextureLayout::Tiled4x4,
+        Some("tiled8x8") => TextureLayout::Tiled8x8,
+        Some("morton") => TextureLayout::Morton,
+        Some("linear") => TextureLayout::Linear,
+        layout => {
+            eprintln!("Unknown texture layout {:?}, expected linear, tiled4x4, tiled8x8 or morton.", layout);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_shading_model(args: &[String]) -> ShadingModel {
+    let Some(i) = args.iter().position(|arg| arg == "--shading") else {
+        return ShadingModel::Lambert;
+    };
+
+    match args.get(i + 1).map(String::as_str) {
+        Some("lambert") => ShadingModel::Lambert,
+        Some("cook-torrance") => ShadingModel::CookTorrance,
+        shading_model => {
+            eprintln!("Unknown shading model {:?}, expected lambert or cook-torrance.", shading_model);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_ambient(args: &[String]) -> Vec3 {
+    let Some(i) = args.iter().position(|arg| arg == "--ambient") else {
+        return Vec3::splat(0.1);
+    };
+
+    match args.get(i + 1).map(|arg| arg.parse::<f32>()) {
+        Some(Ok(ambient)) => Vec3::splat(ambient),
+        _ => {
+            eprintln!("Expected an intensity after --ambient.");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Exposure value in stops, returned as a multiplier.
+fn parse_exposure(args: &[String]) -> f32 {
+    let Some(i) = args.iter().position(|arg| arg == "--exposure") else {
+        return 1.0;
+    };
+
+    match args.get(i + 1).map(|arg| arg.parse::<f32>()) {
+        Some(Ok(ev)) => 2.0f32.powf(ev),
+        _ => {
+            eprintln!("Expected an exposure value after --exposure.");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_auto_exposure(args: &[String]) -> Option<AutoExposure> {
+    let i = args.iter().position(|arg| arg == "--auto-exposure")?;
+
+    let mut auto_exposure = AutoExposure::default();
+    if let Some(Ok(speed)) = args.get(i + 1).map(|arg| arg.parse::<f32>()) {
+        auto_exposure.adaptation_speed = speed;
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--exposure-range") {
+        match (args.get(i + 1).map(|arg| arg.parse::<f32>()), args.get(i + 2).map(|arg| arg.parse::<f32>())) {
+            (Some(Ok(min_ev)), Some(Ok(max_ev))) if min_ev < max_ev => {
+                auto_exposure.min_ev = min_ev;
+                auto_exposure.max_ev = max_ev;
+            },
+            _ => {
+                eprintln!("Expected a minimum and maximum EV after --exposure-range.");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Some(auto_exposure)
+}
+
+fn parse_tone_mapping(args: &[String]) -> ToneMapping {
+    let Some(i) = args.iter().position(|arg| arg == "--tone-mapping") else {
+        return ToneMapping::Linear;
+    };
+
+    match args.get(i + 1).map(String::as_str) {
+        Some("linear") => ToneMapping::Linear,
+        Some("reinhard") => ToneMapping::Reinhard,
+        Some("aces") => ToneMapping::AcesFilmic,
+        Some("uncharted2") => ToneMapping::Uncharted2,
+        Some("agx") => ToneMapping::AgX,
+        tone_mapping => {
+            eprintln!("Unknown tone mapping {:?}, expected linear, reinhard, aces, uncharted2 or agx.", tone_mapping);
+            std::process::exit(1);
+        }
+    }
+}
+
 fn draw_model(
-    framebuffer: &mut Framebuffer,
+    framebuffer: &mut HdrFramebuffer,
     depth_buffer: &mut Framebuffer,
     model: &Model,
-    mvp: &Mat4,
-    inv_trans_model_matrix: &Mat4
+    instances: &[MeshInstance],
+    uniforms: &Uniforms
 ) {
-    for mesh in &model.meshes {
+    for instance in instances {
+        let model_matrix = uniforms.model_matrix * instance.transform;
+        let uniforms = Uniforms {
+            mvp: uniforms.mvp * instance.transform,
+            model_matrix,
+            inv_trans_model_matrix: model_matrix.inverse().transpose(),
+            ..*uniforms
+        };
+        // Mirroring transforms flip the winding of the triangles, so they are reversed to keep their front faces.
+        let mirrored = model_matrix.determinant() < 0.0;
+
+        let mesh = instance.mesh;
         for i in 0..(mesh.indices.len() / 3) {
-            let v0 = mesh.vertices[mesh.indices[i * 3] as usize];
-            let v1 = mesh.vertices[mesh.indices[i * 3 + 1] as usize];
-            let v2 = mesh.vertices[mesh.indices[i * 3 + 2] as usize];
+            let v0 = instance.vertices[mesh.indices[i * 3] as usize];
+            let v1 = instance.vertices[mesh.indices[i * 3 + 1] as usize];
+            let v2 = instance.vertices[mesh.indices[i * 3 + 2] as usize];
+            let (v1, v2) = if mirrored { (v2, v1) } else { (v1, v2) };
 
             let material = &model.materials[mesh.material_idx];
 
@@ -125,47 +566,452 @@ fn draw_model(
                 framebuffer,
                 depth_buffer,
                 &v0, &v1, &v2,
-                mvp,
-                inv_trans_model_matrix,
+                &uniforms,
                 material
             );
         }
     }
 }
 
+fn draw_model_depth(
+    depth_buffer: &mut Framebuffer,
+    instances: &[MeshInstance],
+    mvp: &Mat4,
+    depth_bias: f32,
+    slope_bias: f32
+) {
+    for instance in instances {
+        let mvp = *mvp * instance.transform;
+        let mesh = instance.mesh;
+        for i in 0..(mesh.indices.len() / 3) {
+            let v0 = instance.vertices[mesh.indices[i * 3] as usize];
+            let v1 = instance.vertices[mesh.indices[i * 3 + 1] as usize];
+            let v2 = instance.vertices[mesh.indices[i * 3 + 2] as usize];
+
+            draw_triangle_depth(
+                depth_buffer,
+                &v0.position, &v1.position, &v2.position,
+                &mvp,
+                depth_bias,
+                slope_bias
+            );
+        }
+    }
+}
+
+fn parse_skinning(args: &[String]) -> SkinningMethod {
+    let Some(i) = args.iter().position(|arg| arg == "--skinning") else {
+        return SkinningMethod::LinearBlend;
+    };
+
+    match args.get(i + 1).map(String::as_str) {
+        Some("linear") => SkinningMethod::LinearBlend,
+        Some("dual-quaternion") => SkinningMethod::DualQuaternion,
+        skinning => {
+            eprintln!("Unknown skinning method {:?}, expected linear or dual-quaternion.", skinning);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Plays the model's animations if it has any, starting with the clip named or indexed by `--animation`.
+fn parse_animation_player(args: &[String], model: &Model) -> Option<AnimationPlayer> {
+    if model.animations.is_empty() {
+        return None;
+    }
+
+    let mut player = AnimationPlayer::new(model);
+    if let Some(i) = args.iter().position(|arg| arg == "--animation") {
+        let clip = args.get(i + 1).and_then(|arg| {
+            let by_name = model.animations.iter().position(|clip| clip.name.as_deref() == Some(arg.as_str()));
+            by_name.or_else(|| arg.parse::<usize>().ok().filter(|clip| *clip < model.animations.len()))
+        });
+        match clip {
+            Some(clip) => player.clip = clip,
+            None => {
+                eprintln!("Expected an animation name or an index below {} after --animation.", model.animations.len());
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--animation-speed") {
+        match args.get(i + 1).map(|arg| arg.parse::<f32>()) {
+            Some(Ok(speed)) => player.speed = speed,
+            _ => {
+                eprintln!("Expected a playback speed after --animation-speed.");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    player.looping = !args.iter().any(|arg| arg == "--no-loop");
+    Some(player)
+}
+
+// Comma separated morph target weights, e.g. `--morph-weights 0.5,0,1`.
+fn parse_morph_weights(args: &[String]) -> Option<Vec<f32>> {
+    let i = args.iter().position(|arg| arg == "--morph-weights")?;
+
+    match args.get(i + 1).map(|arg| arg.split(',').map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()) {
+        Some(Ok(weights)) => Some(weights),
+        _ => {
+            eprintln!("Expected comma separated weights after --morph-weights.");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Projection of the camera used when the model's own cameras aren't, `--projection perspective|infinite|orthographic`.
+fn parse_projection(args: &[String]) -> Projection {
+    let perspective = Projection::Perspective { fov_y: (60.0f32).to_radians(), near: 0.01, far: Some(300.0) };
+    let Some(i) = args.iter().position(|arg| arg == "--projection") else {
+        return perspective;
+    };
+
+    match args.get(i + 1).map(String::as_str) {
+        Some("perspective") => perspective,
+        Some("infinite") => Projection::Perspective { fov_y: (60.0f32).to_radians(), near: 0.01, far: None },
+        Some("orthographic") => Projection::Orthographic { y_mag: 1.0, near: 0.01, far: 300.0 },
+        projection => {
+            eprintln!("Unknown projection {:?}, expected perspective, infinite or orthographic.", projection);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Index of the model camera chosen with `--camera`, the scene is viewed from outside if `None`.
+fn parse_camera(args: &[String], model: &Model) -> Option<usize> {
+    let i = args.iter().position(|arg| arg == "--camera")?;
+
+    let camera_count = model.cameras().len();
+    match args.get(i + 1).map(|arg| arg.parse::<usize>()) {
+        Some(Ok(camera)) if camera < camera_count => Some(camera),
+        _ => {
+            eprintln!("Expected a camera index below {} after --camera.", camera_count);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_scene(args: &[String]) -> Option<usize> {
+    let i = args.iter().position(|arg| arg == "--scene")?;
+
+    match args.get(i + 1).map(|arg| arg.parse::<usize>()) {
+        Some(Ok(scene)) => Some(scene),
+        _ => {
+            eprintln!("Expected a scene index after --scene.");
+            std::process::exit(1);
+        }
+    }
+}
+
+// How missing normals are generated, if chosen with `--normals`.
+fn parse_normal_generation(args: &[String]) -> Option<NormalGeneration> {
+    let i = args.iter().position(|arg| arg == "--normals")?;
+
+    match args.get(i + 1).map(String::as_str) {
+        Some("flat") => Some(NormalGeneration::Flat),
+        Some("smooth") => Some(NormalGeneration::Smooth),
+        normals => {
+            eprintln!("Unknown normal generation {:?}, expected flat or smooth.", normals);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Loads the model given with `--model`, picking the importer from its file extension.
+fn load_model(args: &[String], texture_cache: &mut TextureCache) -> Result<Model, LoadError> {
+    let file_path = args
+        .iter()
+        .position(|arg| arg == "--model")
+        .and_then(|i| args.get(i + 1))
+        .map_or("assets/DamagedHelmet/DamagedHelmet.gltf", String::as_str);
+
+    let extension = Path::new(file_path).extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("obj") => load_obj_with_cache(file_path, texture_cache),
+        // CAD models have hard edges, scans are sampled from smooth surfaces.
+        Some("stl") => load_stl(file_path, parse_normal_generation(args).unwrap_or(NormalGeneration::Flat)),
+        Some("ply") => load_ply(file_path, parse_normal_generation(args).unwrap_or(NormalGeneration::Smooth)),
+        _ => load_model_with_cache(file_path, parse_scene(args), texture_cache)
+    }
+}
+
+fn parse_shadow_settings(args: &[String]) -> Option<ShadowSettings> {
+    let i = args.iter().position(|arg| arg == "--shadows")?;
+
+    let mut settings = ShadowSettings::default();
+    if let Some(resolution) = args.get(i + 1).filter(|arg| !arg.starts_with("--")) {
+        match resolution.parse::<usize>() {
+            Ok(resolution) if resolution > 0 => settings.resolution = resolution,
+            _ => {
+                eprintln!("Invalid shadow map resolution {:?}.", resolution);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--shadow-cascades") {
+        match args.get(i + 1).map(|arg| arg.parse::<usize>()) {
+            Some(Ok(count)) if count > 0 => settings.cascade_count = count,
+            _ => {
+                eprintln!("Expected a cascade count after --shadow-cascades.");
+                std::process::exit(1);
+            }
+        }
+        if let Some(Ok(lambda)) = args.get(i + 2).map(|arg| arg.parse::<f32>()) {
+            settings.cascade_split_lambda = lambda.clamp(0.0, 1.0);
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--shadow-filter") {
+        let parameter = args.get(i + 2).and_then(|arg| arg.parse::<f32>().ok());
+        settings.filter = match args.get(i + 1).map(String::as_str) {
+            Some("hard") => ShadowFilter::Hard,
+            Some("pcf") => ShadowFilter::Pcf { kernel_size: parameter.map_or(3, |size| size as usize) },
+            Some("poisson") => ShadowFilter::Poisson { radius: parameter.unwrap_or(2.0) },
+            Some("pcss") => ShadowFilter::Pcss { light_size: parameter.unwrap_or(0.05) },
+            filter => {
+                eprintln!("Unknown shadow filter {:?}, expected hard, pcf, poisson or pcss.", filter);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    Some(settings)
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if let Some(i) = args.iter().position(|arg| arg == "--benchmark-texture-layout") {
+        let file_path = args.get(i + 1).map_or("assets/DamagedHelmet/Default_albedo.jpg", String::as_str);
+        match load_texture(file_path) {
+            Ok(texture) => run_texture_layout_benchmark(&texture),
+            Err(err) => {
+                eprintln!("Failed to load texture: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let mut window = Window::new("3D graphics from scratch! (PART 3)", 512, 512);
     let mut depth_buffer = Framebuffer::new(window.framebuffer().width(), window.framebuffer().height());
+    let mut hdr_buffer = HdrFramebuffer::new(window.framebuffer().width(), window.framebuffer().height());
 
-    let model = load_model("assets/DamagedHelmet/DamagedHelmet.gltf");
+    let mut texture_cache = TextureCache::with_layout(parse_texture_layout(&args));
+    let mut model = match load_model(&args, &mut texture_cache) {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("Failed to load model: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    model.skinning = parse_skinning(&args);
+    // Weights set here are the rest pose, animated weight channels still override them.
+    if let Some(weights) = parse_morph_weights(&args) {
+        for node in model.nodes.iter_mut().filter(|node| !node.weights.is_empty()) {
+            for (weight, value) in node.weights.iter_mut().zip(&weights) {
+                *weight = *value;
+            }
+        }
+    }
+
+    for extension in &model.unsupported_extensions {
+        eprintln!("Warning: the model uses unsupported extension {}, which is ignored.", extension);
+    }
+
+    let environment_map = match load_environment(&args) {
+        Ok(environment_map) => environment_map,
+        Err(err) => {
+            eprintln!("Failed to load environment: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let environment = environment_map.as_ref().map(Environment::from_cubemap);
+
+    let skybox = match load_skybox(&args) {
+        Ok(skybox) => skybox.or(environment_map),
+        Err(err) => {
+            eprintln!("Failed to load skybox: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let shading_model = parse_shading_model(&args);
+    let ambient = parse_ambient(&args);
+    let shadow_settings = parse_shadow_settings(&args);
+    let debug_cascades = args.iter().any(|arg| arg == "--debug-cascades");
+    let exposure = parse_exposure(&args);
+    let mut auto_exposure = parse_auto_exposure(&args);
+    let tone_mapping = parse_tone_mapping(&args);
+    let mut animation_player = parse_animation_player(&args, &model);
+    let model_camera = parse_camera(&args, &model);
+    let default_camera = Camera {
+        transform: Mat4::from_translation(Vec3::new(0.0, 0.0, 2.5)),
+        ..Camera::new(parse_projection(&args))
+    };
+
+    // Models without lights of their own are lit by a single directional light. An intensity of pi
+    // makes a white lambertian surface facing the light come out white.
+    let default_lights = [Light {
+        direction: Vec3::new(0.3, -0.8, -0.4).normalize(),
+        intensity: std::f32::consts::PI,
+        ..Default::default()
+    }];
 
     let timer = SystemTime::now();
+    let mut last_frame_time = 0.0;
 
     while !window.should_close() {
+        let frame_time = timer.elapsed().unwrap().as_secs_f32();
+        let delta_time = frame_time - last_frame_time;
+        last_frame_time = frame_time;
+
+        // Space pauses the animation, N fades into the next clip.
+        if let Some(player) = &mut animation_player {
+            if window.is_key_pressed(minifb::Key::Space) {
+                if player.playing { player.pause() } else { player.play() }
+            }
+            if window.is_key_pressed(minifb::Key::N) {
+                player.cross_fade((player.clip + 1) % model.animations.len(), 0.5);
+            }
+            player.update(&mut model, delta_time);
+        }
+        // Meshes are deformed once per frame and shared by the shadow and color passes.
+        let instances = model.mesh_instances();
+        let model_bounds = bounding_sphere(&instances);
+        let model_lights = model.lights();
+
         let framebuffer = window.framebuffer();
 
         if framebuffer.width() != depth_buffer.width() || framebuffer.height() != depth_buffer.height() {
             depth_buffer = Framebuffer::new(framebuffer.width(), framebuffer.height());
+            hdr_buffer = HdrFramebuffer::new(framebuffer.width(), framebuffer.height());
         }
 
-        framebuffer.clear(from_u8_rgb(20, 20, 20));
+        hdr_buffer.clear(Vec4::new(20.0 / 255.0, 20.0 / 255.0, 20.0 / 255.0, 1.0));
         depth_buffer.clear(u32::MAX);
 
-        let aspect_ratio = framebuffer.width() as f32 / framebuffer.height() as f32;
-        let model_matrix = Mat4::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), timer.elapsed().unwrap().as_secs_f32()) * Mat4::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), (90.0f32).to_radians());
-        let view_matrix = Mat4::from_translation(Vec3::new(0.0, 0.0, -2.5));
-        let proj_matrix = Mat4::perspective_rh((60.0f32).to_radians(), aspect_ratio, 0.01, 300.0);
-        let mvp_matrix = proj_matrix * view_matrix * model_matrix;
-        let inv_trans_model_matrix = model_matrix.inverse().transpose();
+        // Static models viewed from outside spin around so they can be seen from every side.
+        let model_matrix = match (&animation_player, model_camera) {
+            (None, None) => Mat4::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), frame_time),
+            _ => Mat4::IDENTITY
+        };
+        let mut camera = match model_camera {
+            Some(i) => model.cameras()[i].transformed(&model_matrix),
+            None => default_camera
+        };
+        camera.resize(framebuffer.width(), framebuffer.height());
+        let view_matrix = camera.view_matrix();
+        let proj_matrix = camera.projection_matrix();
+        let lights: Vec<Light> = if model_lights.is_empty() {
+            default_lights.to_vec()
+        } else {
+            model_lights.iter().map(|light| light.transformed(&model_matrix)).collect()
+        };
+        let lights: Vec<Light> = lights
+            .into_iter()
+            .map(|light| Light { shadow: light.shadow.or(shadow_settings), ..light })
+            .collect();
+
+        let bounds = (model_matrix.transform_point3(model_bounds.0), model_bounds.1);
+        let shadow_maps: Vec<Option<LightShadow>> = lights
+            .iter()
+            .map(|light| {
+                let settings = light.shadow.as_ref()?;
+                LightShadow::render(light, settings, &instances, &model_matrix, bounds, &camera)
+            })
+            .collect();
+
+        let uniforms = Uniforms {
+            mvp: proj_matrix * view_matrix * model_matrix,
+            model_matrix,
+            inv_trans_model_matrix: model_matrix.inverse().transpose(),
+            camera_position: camera.position(),
+            environment: environment.as_ref(),
+            shading_model,
+            lights: &lights,
+            shadow_maps: &shadow_maps,
+            debug_cascades,
+            ambient
+        };
 
         draw_model(
-            framebuffer,
+            &mut hdr_buffer,
             &mut depth_buffer,
             &model,
-            &mvp_matrix,
-            &inv_trans_model_matrix
+            &instances,
+            &uniforms
         );
 
+        if let Some(skybox) = &skybox {
+            draw_skybox(
+                &mut hdr_buffer,
+                &mut depth_buffer,
+                skybox,
+                &(proj_matrix * view_matrix).inverse()
+            );
+        }
+
+        // The manual exposure compensates on top of the automatic one.
+        let exposure = match &mut auto_exposure {
+            Some(auto_exposure) => exposure * auto_exposure.update(&hdr_buffer, delta_time),
+            None => exposure
+        };
+        hdr_buffer.resolve(framebuffer, exposure, tone_mapping);
+
         window.display();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws a triangle facing the camera through a root node with the given scale, returns whether it covered the center.
+    fn draws_triangle(scale: Vec3) -> bool {
+        let vertex = |x: f32, y: f32| Vertex {
+            position: Vec3::new(x, y, 0.0),
+            normal: Vec3::Z,
+            ..Default::default()
+        };
+        let mesh = Mesh {
+            vertices: vec![vertex(-0.5, -0.5), vertex(0.5, -0.5), vertex(0.0, 0.5)],
+            indices: vec![0, 1, 2],
+            material_idx: 0,
+            targets: Vec::new()
+        };
+        let mut model = Model::from_meshes(vec![mesh], vec![Material::default()]);
+        model.nodes[0].scale = scale;
+
+        let mut framebuffer = HdrFramebuffer::new(8, 8);
+        let mut depth_buffer = Framebuffer::new(8, 8);
+        depth_buffer.clear(u32::MAX);
+        let uniforms = Uniforms {
+            mvp: Mat4::IDENTITY,
+            model_matrix: Mat4::IDENTITY,
+            inv_trans_model_matrix: Mat4::IDENTITY,
+            camera_position: Vec3::Z,
+            environment: None,
+            shading_model: ShadingModel::Lambert,
+            lights: &[],
+            shadow_maps: &[],
+            debug_cascades: false,
+            ambient: Vec3::ONE
+        };
+        draw_model(&mut framebuffer, &mut depth_buffer, &model, &model.mesh_instances(), &uniforms);
+        framebuffer.get_pixel(4, 4) != Vec4::ZERO
+    }
+
+    #[test]
+    fn draws_mirrored_nodes() {
+        assert!(draws_triangle(Vec3::ONE));
+        assert!(draws_triangle(Vec3::new(-1.0, 1.0, 1.0)));
+        assert!(draws_triangle(Vec3::new(1.0, -1.0, 1.0)));
+        // Mirroring all axes turns the front face away from the camera, so it is still culled.
+        assert!(!draws_triangle(Vec3::splat(-1.0)));
+    }
+}
@@ -1,12 +1,36 @@
 use glam::*;
-use crate::{Texture, load_texture};
+use gltf::json::Value;
+use gltf::animation::util::ReadOutputs;
+use crate::{Texture, TextureCache, TextureKey, ColorSpace, load_texture_from_memory, LoadError, generate_tangents, Light, Camera};
+use crate::{SkinningMethod, skin_vertices, morph_vertices, AnimationClip, Channel, ChannelValues, Interpolation};
+use std::borrow::Cow;
 use std::path::Path;
+use std::sync::Arc;
+
+// Extensions that are loaded and rendered, any others in a file are reported in `Model::unsupported_extensions`.
+const SUPPORTED_EXTENSIONS: [&str; 7] = [
+    "KHR_lights_punctual",
+    "KHR_materials_unlit",
+    "KHR_materials_emissive_strength",
+    "KHR_materials_transmission",
+    "KHR_materials_clearcoat",
+    "KHR_materials_sheen",
+    "KHR_texture_transform"
+];
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vertex {
     pub position: Vec3,
     pub normal: Vec3,
-    pub tex_coord: Vec2
+    // The w component is the handedness of the bitangent, which is cross(normal, tangent) * w.
+    pub tangent: Vec4,
+    pub tex_coord: Vec2,
+    pub tex_coord_1: Vec2,
+    // COLOR_0, multiplied with the base color.
+    pub color: Vec4,
+    // Indices into the joints of the skin and how much each of them moves the vertex.
+    pub joints: [u16; 4],
+    pub weights: Vec4
 }
 
 impl Default for Vertex {
@@ -14,7 +38,12 @@ impl Default for Vertex {
         Vertex {
             position: Vec3::ZERO,
             normal: Vec3::ZERO,
-            tex_coord: Vec2::ZERO
+            tangent: Vec4::ZERO,
+            tex_coord: Vec2::ZERO,
+            tex_coord_1: Vec2::ZERO,
+            color: Vec4::ONE,
+            joints: [0; 4],
+            weights: Vec4::ZERO
         }
     }
 }
@@ -23,127 +52,772 @@ impl Default for Vertex {
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
-    pub material_idx: usize
+    pub material_idx: usize,
+    // Blend shapes, weighted by the `weights` of the node drawing the mesh.
+    pub targets: Vec<MorphTarget>
+}
+
+// Offsets added to each vertex of a mesh, scaled by the target's weight. Attributes the target doesn't move are empty.
+#[derive(Clone, Debug)]
+pub struct MorphTarget {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub tangents: Vec<Vec3>
+}
+
+// A node of the glTF scene graph. Its transform is kept as translation, rotation and scale so it can be animated.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    // Indices into `Model::meshes` of the primitives of the node's mesh.
+    pub meshes: Vec<usize>,
+    // Light from KHR_lights_punctual, in the node's space.
+    pub light: Option<Light>,
+    pub camera: Option<Camera>,
+    // Index into `Model::skins` of the skin deforming the node's mesh.
+    pub skin: Option<usize>,
+    // Morph target weights of the node's mesh.
+    pub weights: Vec<f32>
+}
+
+impl Node {
+    // Transform from the node's space to its parent's.
+    pub fn local_transform(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Skin {
+    // Nodes acting as the joints, indexed by the vertices' `joints`.
+    pub joints: Vec<usize>,
+    // Transforms from model space to the space of each joint in the bind pose.
+    pub inverse_bind_matrices: Vec<Mat4>
+}
+
+// A mesh as drawn by a node, with its vertices deformed by the node's morph weights and skin.
+pub struct MeshInstance<'a> {
+    pub mesh: &'a Mesh,
+    pub vertices: Cow<'a, [Vertex]>,
+    // Transform from the space of `vertices` to model space.
+    pub transform: Mat4
 }
 
 #[derive(Clone, Debug)]
 pub struct Model {
+    // Primitives of all meshes in the file, in the space of the nodes using them.
     pub meshes: Vec<Mesh>,
-    pub materials: Vec<Material>
+    pub materials: Vec<Material>,
+    // All nodes of the file, indexed like the glTF nodes.
+    pub nodes: Vec<Node>,
+    // Root nodes of the loaded scene, only these and their descendants are drawn.
+    pub root_nodes: Vec<usize>,
+    pub skins: Vec<Skin>,
+    pub skinning: SkinningMethod,
+    pub animations: Vec<AnimationClip>,
+    // Extensions used by the file that are ignored when rendering it.
+    pub unsupported_extensions: Vec<String>
+}
+
+impl Model {
+    // A model without a scene graph, drawing every mesh with a single root node.
+    pub fn from_meshes(meshes: Vec<Mesh>, materials: Vec<Material>) -> Self {
+        let root = Node {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+            parent: None,
+            children: Vec::new(),
+            meshes: (0..meshes.len()).collect(),
+            light: None,
+            camera: None,
+            skin: None,
+            weights: Vec::new()
+        };
+
+        Model {
+            meshes,
+            materials,
+            nodes: vec![root],
+            root_nodes: vec![0],
+            skins: Vec::new(),
+            skinning: SkinningMethod::LinearBlend,
+            animations: Vec::new(),
+            unsupported_extensions: Vec::new()
+        }
+    }
+
+    // Model space transform of every node, `None` for nodes outside the loaded scene.
+    pub fn world_transforms(&self) -> Vec<Option<Mat4>> {
+        let mut transforms = vec![None; self.nodes.len()];
+        let mut stack: Vec<(usize, Mat4)> = self.root_nodes.iter().map(|root| (*root, Mat4::IDENTITY)).collect();
+        while let Some((node, parent_transform)) = stack.pop() {
+            let transform = parent_transform * self.nodes[node].local_transform();
+            transforms[node] = Some(transform);
+            stack.extend(self.nodes[node].children.iter().map(|child| (*child, transform)));
+        }
+        transforms
+    }
+
+    // Model space transform of `node` found through its parents, for joints outside the loaded scene.
+    fn parent_chain_transform(&self, node: usize) -> Mat4 {
+        let mut transform = self.nodes[node].local_transform();
+        let mut parent = self.nodes[node].parent;
+        while let Some(node) = parent {
+            transform = self.nodes[node].local_transform() * transform;
+            parent = self.nodes[node].parent;
+        }
+        transform
+    }
+
+    // Transforms taking each joint of `skin` from the bind pose to its current pose in model space.
+    pub fn joint_matrices(&self, skin: &Skin, world_transforms: &[Option<Mat4>]) -> Vec<Mat4> {
+        skin.joints
+            .iter()
+            .zip(&skin.inverse_bind_matrices)
+            .map(|(joint, inverse_bind_matrix)| {
+                let transform = world_transforms[*joint].unwrap_or_else(|| self.parent_chain_transform(*joint));
+                transform * *inverse_bind_matrix
+            })
+            .collect()
+    }
+
+    // Every mesh drawn by the scene with its model space transform. Meshes used by several nodes appear once per node.
+    pub fn mesh_instances(&self) -> Vec<MeshInstance<'_>> {
+        let world_transforms = self.world_transforms();
+
+        let mut instances = Vec::new();
+        for (node, transform) in self.nodes.iter().zip(&world_transforms) {
+            let Some(transform) = transform else {
+                continue;
+            };
+            let joint_matrices = node.skin.map(|skin| self.joint_matrices(&self.skins[skin], &world_transforms));
+
+            for mesh in &node.meshes {
+                let mesh = &self.meshes[*mesh];
+                let mut vertices = Cow::Borrowed(mesh.vertices.as_slice());
+                if node.weights.iter().any(|weight| *weight != 0.0) && !mesh.targets.is_empty() {
+                    vertices = Cow::Owned(morph_vertices(&vertices, &mesh.targets, &node.weights));
+                }
+
+                instances.push(match &joint_matrices {
+                    // Skinned meshes are placed by their joints alone, the transform of their node is ignored.
+                    Some(joint_matrices) => MeshInstance {
+                        mesh,
+                        vertices: Cow::Owned(skin_vertices(&vertices, joint_matrices, self.skinning)),
+                        transform: Mat4::IDENTITY
+                    },
+                    None => MeshInstance {
+                        mesh,
+                        vertices,
+                        transform: *transform
+                    }
+                });
+            }
+        }
+        instances
+    }
+
+    // Lights of the scene in model space.
+    pub fn lights(&self) -> Vec<Light> {
+        self.nodes
+            .iter()
+            .zip(self.world_transforms())
+            .filter_map(|(node, transform)| Some(node.light?.transformed(&transform?)))
+            .collect()
+    }
+
+    // Cameras of the scene in model space.
+    pub fn cameras(&self) -> Vec<Camera> {
+        self.nodes
+            .iter()
+            .zip(self.world_transforms())
+            .filter_map(|(node, transform)| Some(node.camera?.transformed(&transform?)))
+            .collect()
+    }
+}
+
+// A texture used by a material, with the texture coordinate set it is sampled with and the
+// KHR_texture_transform applied to those coordinates.
+#[derive(Clone, Debug)]
+pub struct MaterialTexture {
+    pub texture: Arc<Texture>,
+    pub tex_coord: u32,
+    pub transform: Affine2
+}
+
+impl MaterialTexture {
+    // Samples with the set `tex_coord` of `tex_coords`, where `uv_areas` are the areas of the triangle in each set.
+    pub fn sample(&self, tex_coords: &[Vec2], uv_areas: &[f32], screen_area: f32) -> Vec4 {
+        let set = (self.tex_coord as usize).min(tex_coords.len() - 1);
+        let uv = self.transform.transform_point2(tex_coords[set]);
+        let lod = self.texture.lod(uv_areas[set] * self.transform.matrix2.determinant().abs(), screen_area);
+        self.texture.sample_pixel_lod(uv.x, uv.y, lod)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Material {
     pub base_color: Vec4,
-    pub base_color_texture: Option<Texture>
+    pub base_color_texture: Option<MaterialTexture>,
+    pub metallic: f32,
+    pub roughness: f32,
+    // Roughness is read from the green channel, metallic from the blue channel.
+    pub metallic_roughness_texture: Option<MaterialTexture>,
+    pub normal_texture: Option<MaterialTexture>,
+    pub normal_scale: f32,
+    // Darkens indirect lighting, read from the red channel.
+    pub occlusion_texture: Option<MaterialTexture>,
+    pub occlusion_strength: f32,
+    pub emissive: Vec3,
+    pub emissive_texture: Option<MaterialTexture>,
+    // KHR_materials_emissive_strength, scales the emissive color past 1.
+    pub emissive_strength: f32,
+    // KHR_materials_unlit, the base color is output without any lighting.
+    pub unlit: bool,
+    // KHR_materials_transmission, fraction of the diffuse light let through the surface. The texture is read from the red channel.
+    pub transmission: f32,
+    pub transmission_texture: Option<MaterialTexture>,
+    // KHR_materials_clearcoat, a dielectric layer on top of the material with its own roughness and normal.
+    // The factor is read from the red channel of its texture, the roughness from the green channel.
+    pub clearcoat: f32,
+    pub clearcoat_texture: Option<MaterialTexture>,
+    pub clearcoat_roughness: f32,
+    pub clearcoat_roughness_texture: Option<MaterialTexture>,
+    pub clearcoat_normal_texture: Option<MaterialTexture>,
+    pub clearcoat_normal_scale: f32,
+    // KHR_materials_sheen, back scattering of cloth like surfaces. The roughness is read from the alpha channel.
+    pub sheen_color: Vec3,
+    pub sheen_color_texture: Option<MaterialTexture>,
+    pub sheen_roughness: f32,
+    pub sheen_roughness_texture: Option<MaterialTexture>
 }
 
 impl Default for Material {
     fn default() -> Self {
         Material {
             base_color: Vec4::ONE,
-            base_color_texture: None
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: Vec3::ZERO,
+            emissive_texture: None,
+            emissive_strength: 1.0,
+            unlit: false,
+            transmission: 0.0,
+            transmission_texture: None,
+            clearcoat: 0.0,
+            clearcoat_texture: None,
+            clearcoat_roughness: 0.0,
+            clearcoat_roughness_texture: None,
+            clearcoat_normal_texture: None,
+            clearcoat_normal_scale: 1.0,
+            sheen_color: Vec3::ZERO,
+            sheen_color_texture: None,
+            sheen_roughness: 0.0,
+            sheen_roughness_texture: None
         }
     }
 }
 
-pub fn load_model(file_path: &str) -> Model {
-    let (document, buffers, _images) = gltf::import(file_path)
-        .expect("Failed to load model.");
+fn gltf_error(file_path: &Path, err: gltf::Error) -> LoadError {
+    match err {
+        gltf::Error::Io(source) => LoadError::Io { path: file_path.to_path_buf(), source },
+        source => LoadError::Gltf { path: file_path.to_path_buf(), source }
+    }
+}
 
-    let mut meshes = Vec::new();
-    let mut materials = vec![Material::default(); document.materials().len()];
+// Reads an image URI through the buffer loader of the gltf crate, which decodes data URIs and percent encoded
+// paths relative to the model, by wrapping it in a document with a single buffer.
+fn read_image_uri(uri: &str, file_path: &Path) -> Result<Vec<u8>, LoadError> {
+    let root = gltf::json::Root {
+        buffers: vec![gltf::json::Buffer {
+            byte_length: gltf::json::validation::USize64(0),
+            name: None,
+            uri: Some(uri.to_string()),
+            extensions: None,
+            extras: Default::default()
+        }],
+        ..Default::default()
+    };
+    let document = gltf::Document::from_json_without_validation(root);
+    let mut buffers = gltf::import_buffers(&document, file_path.parent(), None).map_err(|err| gltf_error(file_path, err))?;
+    Ok(buffers.remove(0).0)
+}
+
+fn load_image(
+    image: &gltf::Image,
+    buffers: &[gltf::buffer::Data],
+    file_path: &Path,
+    color_space: ColorSpace,
+    texture_cache: &mut TextureCache
+) -> Result<Arc<Texture>, LoadError> {
+    match image.source() {
+        gltf::image::Source::Uri { uri, .. } => {
+            // Files are shared with other models using them, data URIs belong to this one.
+            let key = if uri.starts_with("data:") {
+                TextureKey::Embedded(file_path.to_path_buf(), image.index())
+            } else {
+                let path = file_path.parent().unwrap_or_else(|| Path::new("./")).join(uri);
+                TextureKey::File(path.canonicalize().unwrap_or(path))
+            };
+            texture_cache.get_or_load(key, color_space, || load_texture_from_memory(&read_image_uri(uri, file_path)?, file_path))
+        },
+        gltf::image::Source::View { view, .. } => {
+            let key = TextureKey::Embedded(file_path.to_path_buf(), image.index());
+            let buffer = &buffers[view.buffer().index()].0;
+            let bytes = view.offset()
+                .checked_add(view.length())
+                .and_then(|end| buffer.get(view.offset()..end))
+                .ok_or_else(|| LoadError::ImageDecode {
+                    path: file_path.to_path_buf(),
+                    reason: format!("buffer view {} of image {} is out of bounds", view.index(), image.index())
+                })?;
+            texture_cache.get_or_load(key, color_space, || load_texture_from_memory(bytes, file_path))
+        }
+    }
+}
+
+// The JSON of a .gltf file or the JSON chunk of a .glb file. The gltf crate drops extensions it doesn't
+// know about, so material extensions are read from here.
+fn raw_json(bytes: &[u8]) -> Result<Value, gltf::Error> {
+    let json = if bytes.starts_with(b"glTF") {
+        gltf::Glb::from_slice(bytes)?.json
+    } else {
+        bytes.into()
+    };
+    gltf::json::deserialize::from_slice(&json).map_err(gltf::Error::Deserialize)
+}
+
+fn json_f32(value: &Value, default: f32) -> f32 {
+    value.as_f64().map_or(default, |value| value as f32)
+}
+
+fn json_vec2(value: &Value, default: Vec2) -> Vec2 {
+    let component = |i: usize| value.get(i).and_then(Value::as_f64).map(|value| value as f32);
+    match (component(0), component(1)) {
+        (Some(x), Some(y)) => Vec2::new(x, y),
+        _ => default
+    }
+}
+
+fn json_vec3(value: &Value, default: Vec3) -> Vec3 {
+    let component = |i: usize| value.get(i).and_then(Value::as_f64).map(|value| value as f32);
+    match (component(0), component(1), component(2)) {
+        (Some(x), Some(y), Some(z)) => Vec3::new(x, y, z),
+        _ => default
+    }
+}
+
+// The KHR_texture_transform of a textureInfo object, the identity if it has none.
+fn texture_transform(info: &Value) -> Affine2 {
+    let transform = &info["extensions"]["KHR_texture_transform"];
+    let offset = json_vec2(&transform["offset"], Vec2::ZERO);
+    let scale = json_vec2(&transform["scale"], Vec2::ONE);
+
+    // Rotation is counter-clockwise in texture space, where v points down.
+    Affine2::from_scale_angle_translation(scale, -json_f32(&transform["rotation"], 0.0), offset)
+}
+
+// Loads `texture` with the texture coordinate set and transform from the JSON of the textureInfo object referencing it.
+// `color_space` is sRGB for textures holding colors and linear for data like normals or roughness.
+fn load_material_texture(
+    texture: &gltf::Texture,
+    info: &Value,
+    buffers: &[gltf::buffer::Data],
+    file_path: &Path,
+    color_space: ColorSpace,
+    texture_cache: &mut TextureCache
+) -> Result<MaterialTexture, LoadError> {
+    // KHR_texture_transform may override the set the texture is sampled with.
+    let tex_coord = info["extensions"]["KHR_texture_transform"]["texCoord"]
+        .as_u64()
+        .or_else(|| info["texCoord"].as_u64())
+        .unwrap_or(0);
+
+    Ok(MaterialTexture {
+        texture: load_image(&texture.source(), buffers, file_path, color_space, texture_cache)?,
+        tex_coord: tex_coord as u32,
+        transform: texture_transform(info)
+    })
+}
+
+// Loads the texture of a textureInfo object of an extension, if it has one.
+fn load_extension_texture(
+    info: &Value,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    file_path: &Path,
+    color_space: ColorSpace,
+    texture_cache: &mut TextureCache
+) -> Result<Option<MaterialTexture>, LoadError> {
+    let Some(index) = info["index"].as_u64() else {
+        return Ok(None);
+    };
+    let texture = document.textures().nth(index as usize).ok_or_else(|| LoadError::Unsupported {
+        path: file_path.to_path_buf(),
+        feature: format!("reference to missing texture {}", index)
+    })?;
+    load_material_texture(&texture, info, buffers, file_path, color_space, texture_cache).map(Some)
+}
+
+fn load_material(
+    material: &gltf::Material,
+    json: &Value,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    file_path: &Path,
+    texture_cache: &mut TextureCache
+) -> Result<Material, LoadError> {
+    let pbr = material.pbr_metallic_roughness();
+    let pbr_json = &json["pbrMetallicRoughness"];
+    let mut result = Material {
+        base_color: Vec4::from(pbr.base_color_factor()),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: Vec3::from(material.emissive_factor()),
+        ..Default::default()
+    };
+
+    if let Some(info) = pbr.base_color_texture() {
+        let texture = load_material_texture(&info.texture(), &pbr_json["baseColorTexture"], buffers, file_path, ColorSpace::Srgb, texture_cache)?;
+        result.base_color_texture = Some(texture);
+    }
+
+    if let Some(info) = pbr.metallic_roughness_texture() {
+        let texture = load_material_texture(&info.texture(), &pbr_json["metallicRoughnessTexture"], buffers, file_path, ColorSpace::Linear, texture_cache)?;
+        result.metallic_roughness_texture = Some(texture);
+    }
+
+    if let Some(normal_texture) = material.normal_texture() {
+        let texture = load_material_texture(&normal_texture.texture(), &json["normalTexture"], buffers, file_path, ColorSpace::Linear, texture_cache)?;
+        result.normal_texture = Some(texture);
+        result.normal_scale = normal_texture.scale();
+    }
+
+    if let Some(occlusion_texture) = material.occlusion_texture() {
+        let texture = load_material_texture(&occlusion_texture.texture(), &json["occlusionTexture"], buffers, file_path, ColorSpace::Linear, texture_cache)?;
+        result.occlusion_texture = Some(texture);
+        result.occlusion_strength = occlusion_texture.strength();
+    }
+
+    if let Some(info) = material.emissive_texture() {
+        let texture = load_material_texture(&info.texture(), &json["emissiveTexture"], buffers, file_path, ColorSpace::Srgb, texture_cache)?;
+        result.emissive_texture = Some(texture);
+    }
+
+    let extensions = &json["extensions"];
+    result.unlit = extensions.get("KHR_materials_unlit").is_some();
+    result.emissive_strength = json_f32(&extensions["KHR_materials_emissive_strength"]["emissiveStrength"], 1.0);
+
+    let transmission = &extensions["KHR_materials_transmission"];
+    result.transmission = json_f32(&transmission["transmissionFactor"], 0.0);
+    result.transmission_texture = load_extension_texture(&transmission["transmissionTexture"], document, buffers, file_path, ColorSpace::Linear, texture_cache)?;
+
+    let clearcoat = &extensions["KHR_materials_clearcoat"];
+    result.clearcoat = json_f32(&clearcoat["clearcoatFactor"], 0.0);
+    result.clearcoat_texture = load_extension_texture(&clearcoat["clearcoatTexture"], document, buffers, file_path, ColorSpace::Linear, texture_cache)?;
+    result.clearcoat_roughness = json_f32(&clearcoat["clearcoatRoughnessFactor"], 0.0);
+    result.clearcoat_roughness_texture =
+        load_extension_texture(&clearcoat["clearcoatRoughnessTexture"], document, buffers, file_path, ColorSpace::Linear, texture_cache)?;
+    result.clearcoat_normal_texture =
+        load_extension_texture(&clearcoat["clearcoatNormalTexture"], document, buffers, file_path, ColorSpace::Linear, texture_cache)?;
+    result.clearcoat_normal_scale = json_f32(&clearcoat["clearcoatNormalTexture"]["scale"], 1.0);
+
+    let sheen = &extensions["KHR_materials_sheen"];
+    result.sheen_color = json_vec3(&sheen["sheenColorFactor"], Vec3::ZERO);
+    result.sheen_color_texture = load_extension_texture(&sheen["sheenColorTexture"], document, buffers, file_path, ColorSpace::Srgb, texture_cache)?;
+    result.sheen_roughness = json_f32(&sheen["sheenRoughnessFactor"], 0.0);
+    result.sheen_roughness_texture =
+        load_extension_texture(&sheen["sheenRoughnessTexture"], document, buffers, file_path, ColorSpace::Linear, texture_cache)?;
+
+    Ok(result)
+}
+
+// Loads a glTF or GLB file. `scene` picks the scene to draw instead of the default one, and decoded textures
+// are shared with other models loaded through the same cache.
+pub fn load_model_with_cache<P: AsRef<Path>>(
+    file_path: P,
+    scene: Option<usize>,
+    texture_cache: &mut TextureCache
+) -> Result<Model, LoadError> {
+    let file_path = file_path.as_ref();
+    let bytes = std::fs::read(file_path).map_err(|source| LoadError::Io {
+        path: file_path.to_path_buf(),
+        source
+    })?;
+    let gltf = gltf::Gltf::from_slice(&bytes).map_err(|err| gltf_error(file_path, err))?;
+    let json = raw_json(&bytes).map_err(|err| gltf_error(file_path, err))?;
+    let buffers = gltf::import_buffers(&gltf.document, file_path.parent(), gltf.blob).map_err(|err| gltf_error(file_path, err))?;
+    let document = gltf.document;
+
+    // Required extensions change how the file must be interpreted, so it can't be rendered without them.
+    if let Some(extension) = document.extensions_required().find(|extension| !SUPPORTED_EXTENSIONS.contains(extension)) {
+        return Err(LoadError::Unsupported {
+            path: file_path.to_path_buf(),
+            feature: format!("required extension {}", extension)
+        });
+    }
+    let unsupported_extensions = document
+        .extensions_used()
+        .filter(|extension| !SUPPORTED_EXTENSIONS.contains(extension))
+        .map(String::from)
+        .collect();
+
+    let mut materials = document
+        .materials()
+        .zip(0..)
+        .map(|(material, i)| load_material(&material, &json["materials"][i], &document, &buffers, file_path, texture_cache))
+        .collect::<Result<Vec<_>, _>>()?;
     if materials.is_empty() {
         materials.push(Material::default());
     }
-    
-    if document.nodes().len() > 0 {
-        process_node(
-            document.nodes().next().as_ref().unwrap(),
-            &buffers,
-            &mut meshes,
-            &mut materials,
-            file_path
-        );
+
+    let mut meshes = Vec::new();
+    let mesh_primitives = document
+        .meshes()
+        .map(|mesh| load_mesh(&mesh, &buffers, &mut meshes, file_path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let skins = document
+        .skins()
+        .map(|skin| {
+            let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+            let inverse_bind_matrices = match skin.reader(|buffer| Some(&buffers[buffer.index()])).read_inverse_bind_matrices() {
+                Some(matrices) => matrices.map(|matrix| Mat4::from_cols_array_2d(&matrix)).collect(),
+                None => vec![Mat4::IDENTITY; joints.len()]
+            };
+            Skin {
+                joints,
+                inverse_bind_matrices
+            }
+        })
+        .collect();
+
+    let animations = document
+        .animations()
+        .map(|animation| load_animation(&animation, &buffers))
+        .collect();
+
+    let mut nodes: Vec<Node> = document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            Node {
+                translation: Vec3::from(translation),
+                rotation: Quat::from_array(rotation),
+                scale: Vec3::from(scale),
+                parent: None,
+                children: node.children().map(|child| child.index()).collect(),
+                meshes: node.mesh().map_or_else(Vec::new, |mesh| mesh_primitives[mesh.index()].clone()),
+                light: node.light().map(|light| Light::from_gltf(&light, &Mat4::IDENTITY)),
+                camera: node.camera().map(|camera| Camera::from_gltf(&camera, &Mat4::IDENTITY)),
+                skin: node.skin().map(|skin| skin.index()),
+                weights: node
+                    .weights()
+                    .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+                    .map(|weights| weights.to_vec())
+                    .unwrap_or_else(|| {
+                        let target_count = node.mesh().and_then(|mesh| mesh.primitives().next()).map_or(0, |primitive| primitive.morph_targets().len());
+                        vec![0.0; target_count]
+                    })
+            }
+        })
+        .collect();
+    for node in 0..nodes.len() {
+        for child in nodes[node].children.clone() {
+            nodes[child].parent = Some(node);
+        }
     }
 
-    Model {
+    // Without a scene to pick, every node without a parent is a root.
+    let scene = scene
+        .or_else(|| document.default_scene().map(|scene| scene.index()))
+        .or_else(|| document.scenes().next().map(|scene| scene.index()));
+    let root_nodes = match scene {
+        Some(scene) => document
+            .scenes()
+            .nth(scene)
+            .ok_or_else(|| LoadError::MissingScene { path: file_path.to_path_buf(), scene })?
+            .nodes()
+            .map(|node| node.index())
+            .collect(),
+        None => (0..nodes.len()).filter(|node| nodes[*node].parent.is_none()).collect()
+    };
+
+    Ok(Model {
         meshes,
-        materials
+        materials,
+        nodes,
+        root_nodes,
+        skins,
+        skinning: SkinningMethod::LinearBlend,
+        animations,
+        unsupported_extensions
+    })
+}
+
+fn load_animation(animation: &gltf::Animation, buffers: &[gltf::buffer::Data]) -> AnimationClip {
+    let channels: Vec<Channel> = animation
+        .channels()
+        .filter_map(|channel| {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times: Vec<f32> = reader.read_inputs()?.collect();
+            let values = match reader.read_outputs()? {
+                ReadOutputs::Translations(values) => ChannelValues::Translation(values.map(Vec3::from).collect()),
+                ReadOutputs::Rotations(values) => ChannelValues::Rotation(values.into_f32().map(Quat::from_array).collect()),
+                ReadOutputs::Scales(values) => ChannelValues::Scale(values.map(Vec3::from).collect()),
+                ReadOutputs::MorphTargetWeights(values) => ChannelValues::Weights(values.into_f32().collect())
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline
+            };
+
+            Some(Channel {
+                node: channel.target().node().index(),
+                interpolation,
+                times,
+                values
+            })
+        })
+        .collect();
+
+    AnimationClip {
+        name: animation.name().map(String::from),
+        duration: channels.iter().filter_map(|channel| channel.times.last().copied()).fold(0.0, f32::max),
+        channels
     }
 }
 
-fn process_node(
-    node: &gltf::Node,
+// Loads the triangle primitives of `mesh` into `meshes`, returning their indices.
+fn load_mesh(
+    mesh: &gltf::Mesh,
     buffers: &[gltf::buffer::Data],
     meshes: &mut Vec<Mesh>,
-    materials: &mut [Material],
-    file_path: &str
-) {
-    if let Some(mesh) = node.mesh() {
-        for primitive in mesh.primitives() {
-            if primitive.mode() == gltf::mesh::Mode::Triangles {
-                let reader = primitive.reader(
-                    |buffer| Some(&buffers[buffer.index()])
-                );
-
-                let positions = {
-                    let iter = reader
-                        .read_positions()
-                        .expect("Failed to process mesh node. (Vertices must have positions)");
-
-                    iter.map(|arr| -> Vec3 { Vec3::from(arr) }).collect::<Vec<_>>()
-                };
-
-                let mut vertices: Vec<Vertex> = positions
-                    .into_iter()
-                    .map(|position| {
-                        Vertex {
-                             position,
-                             ..Default::default()
-                        }
-                }).collect();
+    file_path: &Path
+) -> Result<Vec<usize>, LoadError> {
+    let mut primitives = Vec::new();
+    for primitive in mesh.primitives() {
+        if primitive.mode() == gltf::mesh::Mode::Triangles {
+            let reader = primitive.reader(
+                |buffer| Some(&buffers[buffer.index()])
+            );
+
+            let missing_attribute = |attribute| LoadError::MissingAttribute {
+                path: file_path.to_path_buf(),
+                mesh: mesh.index(),
+                primitive: primitive.index(),
+                attribute
+            };
+
+            let positions = {
+                let iter = reader
+                    .read_positions()
+                    .ok_or_else(|| missing_attribute("POSITION"))?;
 
-                if let Some(normals) = reader.read_normals() {
-                    for (i, normal) in normals.enumerate() {
-                        vertices[i].normal = Vec3::from(normal);
+                iter.map(|arr| -> Vec3 { Vec3::from(arr) }).collect::<Vec<_>>()
+            };
+
+            let mut vertices: Vec<Vertex> = positions
+                .into_iter()
+                .map(|position| {
+                    Vertex {
+                         position,
+                         ..Default::default()
                     }
+            }).collect();
+
+            if let Some(normals) = reader.read_normals() {
+                for (i, normal) in normals.enumerate() {
+                    vertices[i].normal = Vec3::from(normal);
                 }
+            }
 
-                if let Some(tex_coords) = reader.read_tex_coords(0) {
-                    for (i, tex_coord) in tex_coords.into_f32().enumerate() {
-                        vertices[i].tex_coord = Vec2::from(tex_coord);
-                    }
+            if let Some(tex_coords) = reader.read_tex_coords(0) {
+                for (i, tex_coord) in tex_coords.into_f32().enumerate() {
+                    vertices[i].tex_coord = Vec2::from(tex_coord);
                 }
+            }
 
-                let indices = reader
-                    .read_indices()
-                    .map(|read_indices| {
-                        read_indices.into_u32().collect::<Vec<_>>()
-                    }).expect("Failed to process mesh node. (Indices are required)");
-                
-                let prim_material = primitive.material();
-                let pbr = prim_material.pbr_metallic_roughness();
-                let material_idx = primitive.material().index().unwrap_or(0);
-
-                let material = &mut materials[material_idx];
-                material.base_color = Vec4::from(pbr.base_color_factor());
-                if let Some(base_color_texture) = pbr.base_color_texture() {
-                    if let gltf::image::Source::Uri { uri, .. } = base_color_texture.texture().source().source() {
-                        let model_path = Path::new(file_path);
-                        let texture_path = model_path.parent().unwrap_or_else(|| Path::new("./")).join(uri);
-                        let texture_path_str = texture_path.into_os_string().into_string().unwrap();
-
-                        material.base_color_texture = Some(load_texture(&texture_path_str));
-                    }
+            if let Some(tex_coords) = reader.read_tex_coords(1) {
+                for (i, tex_coord) in tex_coords.into_f32().enumerate() {
+                    vertices[i].tex_coord_1 = Vec2::from(tex_coord);
                 }
+            }
 
-                meshes.push(Mesh {
-                    vertices,
-                    indices,
-                    material_idx
-                });
+            if let Some(colors) = reader.read_colors(0) {
+                for (i, color) in colors.into_rgba_f32().enumerate() {
+                    vertices[i].color = Vec4::from(color);
+                }
             }
+
+            if let Some(joints) = reader.read_joints(0) {
+                for (i, joints) in joints.into_u16().enumerate() {
+                    vertices[i].joints = joints;
+                }
+            }
+
+            if let Some(weights) = reader.read_weights(0) {
+                for (i, weights) in weights.into_f32().enumerate() {
+                    vertices[i].weights = Vec4::from(weights);
+                }
+            }
+
+            let mut indices = reader
+                .read_indices()
+                .map(|read_indices| {
+                    read_indices.into_u32().collect::<Vec<_>>()
+                }).ok_or_else(|| missing_attribute("indices"))?;
+
+            let mut targets: Vec<MorphTarget> = reader
+                .read_morph_targets()
+                .map(|(positions, normals, tangents)| MorphTarget {
+                    positions: positions.map_or_else(Vec::new, |positions| positions.map(Vec3::from).collect()),
+                    normals: normals.map_or_else(Vec::new, |normals| normals.map(Vec3::from).collect()),
+                    tangents: tangents.map_or_else(Vec::new, |tangents| tangents.map(Vec3::from).collect())
+                })
+                .collect();
+
+            if let Some(tangents) = reader.read_tangents() {
+                for (i, tangent) in tangents.enumerate() {
+                    vertices[i].tangent = Vec4::from(tangent);
+                }
+            } else {
+                // Copies made for mirrored texture coordinates move like the vertex they were copied from.
+                let copied_from = generate_tangents(&mut vertices, &mut indices);
+                for target in &mut targets {
+                    for offsets in [&mut target.positions, &mut target.normals, &mut target.tangents] {
+                        if !offsets.is_empty() {
+                            for index in &copied_from {
+                                offsets.push(offsets[*index as usize]);
+                            }
+                        }
+                    }
+                }
+            }
+            
+            let material_idx = primitive.material().index().unwrap_or(0);
+
+            primitives.push(meshes.len());
+            meshes.push(Mesh {
+                vertices,
+                indices,
+                material_idx,
+                targets
+            });
         }
     }
-}
\ No newline at end of file
+
+    Ok(primitives)
+}
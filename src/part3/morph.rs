@@ -0,0 +1,27 @@
+use glam::*;
+use crate::{MorphTarget, Vertex};
+
+// Adds the offsets of `targets` scaled by `weights` to `vertices`. Normals and tangents are renormalized.
+pub fn morph_vertices(vertices: &[Vertex], targets: &[MorphTarget], weights: &[f32]) -> Vec<Vertex> {
+    let mut morphed = vertices.to_vec();
+    for (target, weight) in targets.iter().zip(weights) {
+        if *weight == 0.0 {
+            continue;
+        }
+        for (vertex, offset) in morphed.iter_mut().zip(&target.positions) {
+            vertex.position += *offset * *weight;
+        }
+        for (vertex, offset) in morphed.iter_mut().zip(&target.normals) {
+            vertex.normal += *offset * *weight;
+        }
+        for (vertex, offset) in morphed.iter_mut().zip(&target.tangents) {
+            vertex.tangent += Vec4::from((*offset * *weight, 0.0));
+        }
+    }
+
+    for vertex in &mut morphed {
+        vertex.normal = vertex.normal.normalize_or_zero();
+        vertex.tangent = Vec4::from((vertex.tangent.xyz().normalize_or_zero(), vertex.tangent.w));
+    }
+    morphed
+}
@@ -0,0 +1,53 @@
+use glam::*;
+use std::collections::HashMap;
+use crate::Vertex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalGeneration {
+    // Every triangle gets its own vertices with its normal, keeping the edges between them hard.
+    Flat,
+    // Vertices at the same position share the area weighted normal of the triangles around them.
+    Smooth
+}
+
+pub fn triangle_normal(a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    (b - a).cross(c - a).normalize_or_zero()
+}
+
+// Bits of a position or direction, with -0.0 turned into 0.0 so both compare equal.
+pub fn position_key(position: Vec3) -> [u32; 3] {
+    (position + Vec3::ZERO).to_array().map(f32::to_bits)
+}
+
+// Replaces the normals of the triangles in `vertices` and `indices`. Flat normals split the vertices
+// shared by triangles, so both may be rewritten.
+pub fn generate_normals(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, method: NormalGeneration) {
+    match method {
+        NormalGeneration::Flat => {
+            let flat_vertices: Vec<Vertex> = indices
+                .chunks_exact(3)
+                .flat_map(|triangle| {
+                    let corners = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+                    let normal = triangle_normal(corners[0].position, corners[1].position, corners[2].position);
+                    corners.map(|vertex| Vertex { normal, ..vertex })
+                })
+                .collect();
+            *indices = (0..flat_vertices.len() as u32).collect();
+            *vertices = flat_vertices;
+        },
+        NormalGeneration::Smooth => {
+            // Unnormalized cross products are weighted by the triangles' areas.
+            let mut normals: HashMap<[u32; 3], Vec3> = HashMap::new();
+            for triangle in indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+                let normal = (b - a).cross(c - a);
+                for position in [a, b, c] {
+                    *normals.entry(position_key(position)).or_insert(Vec3::ZERO) += normal;
+                }
+            }
+            for vertex in vertices.iter_mut() {
+                vertex.normal = normals.get(&position_key(vertex.position)).map_or(Vec3::ZERO, |normal| normal.normalize_or_zero());
+            }
+        }
+    }
+}
@@ -0,0 +1,471 @@
+use glam::*;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::{Model, Mesh, Material, MaterialTexture, Vertex, TextureCache, ColorSpace, LoadError, generate_tangents};
+
+// Vertex attributes of an OBJ file, referenced by the corners of its faces.
+struct Attributes {
+    positions: Vec<Vec3>,
+    // Optional color following a position, white for positions without one.
+    colors: Vec<Vec4>,
+    tex_coords: Vec<Vec2>,
+    normals: Vec<Vec3>
+}
+
+// Indices into `Attributes` of one corner of a face, the texture coordinate and normal are optional.
+#[derive(Clone, Copy)]
+struct Corner {
+    position: usize,
+    tex_coord: Option<usize>,
+    normal: Option<usize>
+}
+
+struct Face {
+    corners: Vec<Corner>,
+    // 0 if smoothing is off for the face.
+    smoothing_group: u32
+}
+
+// Faces of one group using the same material, they become one mesh.
+struct FaceGroup {
+    material_idx: usize,
+    faces: Vec<Face>
+}
+
+// Where the normal of a vertex comes from, corners with the same attributes and normal source share a vertex.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    File(usize),
+    // Average of the faces around a position in a smoothing group.
+    Smooth(u32, usize),
+    // Normal of a face without smoothing.
+    Flat(usize)
+}
+
+fn read_source(file_path: &Path) -> Result<String, LoadError> {
+    std::fs::read_to_string(file_path).map_err(|source| LoadError::Io {
+        path: file_path.to_path_buf(),
+        source
+    })
+}
+
+// The keyword and arguments of each statement of an OBJ or MTL file, with its line number.
+fn statements(source: &str) -> impl Iterator<Item = (usize, &str, Vec<&str>)> {
+    source.lines().enumerate().filter_map(|(i, line)| {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next()?;
+        Some((i + 1, keyword, tokens.collect()))
+    })
+}
+
+// At least `count` numbers from the start of `arguments`, followed by any optional ones.
+fn parse_floats(arguments: &[&str], count: usize, file_path: &Path, line: usize) -> Result<Vec<f32>, LoadError> {
+    let values = arguments
+        .iter()
+        .map(|argument| argument.parse::<f32>().map_err(|_| LoadError::parse(file_path, line, format!("invalid number {:?}", argument))))
+        .collect::<Result<Vec<f32>, LoadError>>()?;
+    if values.len() < count {
+        return Err(LoadError::parse(file_path, line, format!("expected {} numbers, found {}", count, values.len())));
+    }
+    Ok(values)
+}
+
+// Resolves a 1-based index, or a negative one relative to the end, into a list of `count` elements.
+fn resolve_index(token: &str, count: usize, file_path: &Path, line: usize) -> Result<usize, LoadError> {
+    let index: i64 = token.parse().map_err(|_| LoadError::parse(file_path, line, format!("invalid index {:?}", token)))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(LoadError::parse(file_path, line, format!("index {} is out of range", index)));
+    }
+    Ok(resolved as usize)
+}
+
+// A face corner written as `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+fn parse_corner(token: &str, attributes: &Attributes, file_path: &Path, line: usize) -> Result<Corner, LoadError> {
+    let mut parts = token.split('/');
+    let position = resolve_index(parts.next().unwrap_or(""), attributes.positions.len(), file_path, line)?;
+    let mut optional = |count: usize| match parts.next() {
+        Some(part) if !part.is_empty() => resolve_index(part, count, file_path, line).map(Some),
+        _ => Ok(None)
+    };
+    let tex_coord = optional(attributes.tex_coords.len())?;
+    let normal = optional(attributes.normals.len())?;
+
+    Ok(Corner {
+        position,
+        tex_coord,
+        normal
+    })
+}
+
+// Normal of a polygon scaled by twice its area, robust to polygons that aren't quite planar.
+fn newell_normal(points: &[Vec3]) -> Vec3 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .fold(Vec3::ZERO, |normal, (a, b)| normal + a.cross(*b))
+}
+
+// Splits a polygon into triangles of its corner indices by clipping ears, which handles concave polygons.
+pub fn triangulate(points: &[Vec3]) -> Vec<[usize; 3]> {
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Work in the plane of the polygon, with its winding turned counter-clockwise.
+    let (u, v) = newell_normal(points).try_normalize().unwrap_or(Vec3::Z).any_orthonormal_pair();
+    let projected: Vec<Vec2> = points.iter().map(|p| Vec2::new(p.dot(u), p.dot(v))).collect();
+    let area = projected.iter().zip(projected.iter().cycle().skip(1)).map(|(a, b)| a.perp_dot(*b)).sum::<f32>();
+    let cross = |a: usize, b: usize, c: usize| (projected[b] - projected[a]).perp_dot(projected[c] - projected[a]) * area.signum();
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let corners = |i: usize| [remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]];
+        let is_ear = |i: usize| {
+            let [a, b, c] = corners(i);
+            cross(a, b, c) > 0.0 && remaining.iter().all(|&p| {
+                p == a || p == b || p == c || cross(a, b, p) < 0.0 || cross(b, c, p) < 0.0 || cross(c, a, p) < 0.0
+            })
+        };
+
+        // Self-intersecting and degenerate polygons have no ears left, the rest is fanned.
+        let Some(ear) = (0..count).find(|i| is_ear(*i)) else {
+            break;
+        };
+        triangles.push(corners(ear));
+        remaining.remove(ear);
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+
+    triangles
+}
+
+// OBJ, STL and PLY materials are dielectrics, the PBR default is a metal.
+pub fn default_material() -> Material {
+    Material {
+        metallic: 0.0,
+        ..Default::default()
+    }
+}
+
+// A texture map statement: options followed by the file name. Returns the texture and its bump multiplier,
+// or None when the image can't be loaded so the material is drawn without it.
+fn load_map(
+    arguments: &[&str],
+    directory: &Path,
+    color_space: ColorSpace,
+    texture_cache: &mut TextureCache,
+    file_path: &Path,
+    line: usize
+) -> Result<Option<(MaterialTexture, f32)>, LoadError> {
+    let is_value = |argument: &str| argument.parse::<f32>().is_ok() || argument == "on" || argument == "off";
+
+    let mut bump_multiplier = 1.0;
+    let mut i = 0;
+    while i < arguments.len() && arguments[i].starts_with('-') {
+        let option = arguments[i];
+        i += 1;
+        // Options take a varying number of values, the last argument is always left for the file name.
+        let value_count = match option {
+            "-imfchan" | "-type" => 1,
+            _ => arguments[i..].iter().take_while(|argument| is_value(argument)).count()
+        }.min(arguments.len().saturating_sub(i + 1));
+        if option == "-bm" && value_count > 0 {
+            bump_multiplier = parse_floats(&arguments[i..i + 1], 1, file_path, line)?[0];
+        }
+        i += value_count;
+    }
+
+    let name = arguments[i..].join(" ");
+    if name.is_empty() {
+        return Err(LoadError::parse(file_path, line, "texture map without a file name".to_string()));
+    }
+    let texture = match texture_cache.load(directory.join(name.replace('\\', "/")), color_space) {
+        Ok(texture) => texture,
+        Err(err) => {
+            eprintln!("Warning: {}, the texture map is ignored.", err);
+            return Ok(None);
+        }
+    };
+
+    Ok(Some((MaterialTexture {
+        texture,
+        tex_coord: 0,
+        transform: Affine2::IDENTITY
+    }, bump_multiplier)))
+}
+
+// Adds the materials of an MTL file to `materials`, and their indices to `material_names`.
+// An unreadable file adds nothing, faces using its materials get the default material.
+fn load_mtl(
+    file_path: &Path,
+    texture_cache: &mut TextureCache,
+    materials: &mut Vec<Material>,
+    material_names: &mut HashMap<String, usize>
+) -> Result<(), LoadError> {
+    let source = match read_source(file_path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Warning: {}, its materials are ignored.", err);
+            return Ok(());
+        }
+    };
+    let directory = file_path.parent().unwrap_or(Path::new(""));
+
+    let mut current = None;
+    for (line, keyword, arguments) in statements(&source) {
+        if keyword == "newmtl" {
+            materials.push(default_material());
+            material_names.insert(arguments.join(" "), materials.len() - 1);
+            current = Some(materials.len() - 1);
+            continue;
+        }
+        let Some(material) = current.map(|i| &mut materials[i]) else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" => {
+                let color = parse_floats(&arguments, 3, file_path, line)?;
+                material.base_color = Vec4::new(color[0], color[1], color[2], material.base_color.w);
+            },
+            // Dissolve may be preceded by -halo, which isn't supported.
+            "d" => {
+                material.base_color.w = parse_floats(&arguments[arguments.len().saturating_sub(1)..], 1, file_path, line)?[0];
+            },
+            "Tr" => {
+                material.base_color.w = 1.0 - parse_floats(&arguments, 1, file_path, line)?[0];
+            },
+            "map_Kd" => {
+                material.base_color_texture = load_map(&arguments, directory, ColorSpace::Srgb, texture_cache, file_path, line)?.map(|(texture, _)| texture);
+            },
+            // Bump maps are expected to hold tangent space normals rather than heights.
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                if let Some((texture, bump_multiplier)) = load_map(&arguments, directory, ColorSpace::Linear, texture_cache, file_path, line)? {
+                    material.normal_texture = Some(texture);
+                    material.normal_scale = bump_multiplier;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn build_mesh(group: &FaceGroup, attributes: &Attributes) -> Mesh {
+    let face_points = |face: &Face| face.corners.iter().map(|corner| attributes.positions[corner.position]).collect::<Vec<Vec3>>();
+
+    // Area weighted normals of the faces around each position of a smoothing group.
+    let mut smooth_normals: HashMap<(u32, usize), Vec3> = HashMap::new();
+    for face in group.faces.iter().filter(|face| face.smoothing_group != 0) {
+        let normal = newell_normal(&face_points(face));
+        for corner in face.corners.iter().filter(|corner| corner.normal.is_none()) {
+            *smooth_normals.entry((face.smoothing_group, corner.position)).or_insert(Vec3::ZERO) += normal;
+        }
+    }
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut unique_vertices: HashMap<(usize, Option<usize>, NormalSource), u32> = HashMap::new();
+    for (face_idx, face) in group.faces.iter().enumerate() {
+        let points = face_points(face);
+        let face_normal = newell_normal(&points).normalize_or_zero();
+
+        for triangle in triangulate(&points) {
+            for corner in triangle.map(|i| face.corners[i]) {
+                let normal_source = match corner.normal {
+                    Some(normal) => NormalSource::File(normal),
+                    None if face.smoothing_group != 0 => NormalSource::Smooth(face.smoothing_group, corner.position),
+                    None => NormalSource::Flat(face_idx)
+                };
+
+                let index = *unique_vertices.entry((corner.position, corner.tex_coord, normal_source)).or_insert_with(|| {
+                    vertices.push(Vertex {
+                        position: attributes.positions[corner.position],
+                        normal: match normal_source {
+                            NormalSource::File(normal) => attributes.normals[normal].normalize_or_zero(),
+                            NormalSource::Smooth(smoothing_group, position) => smooth_normals[&(smoothing_group, position)].normalize_or_zero(),
+                            NormalSource::Flat(_) => face_normal
+                        },
+                        tex_coord: corner.tex_coord.map_or(Vec2::ZERO, |tex_coord| attributes.tex_coords[tex_coord]),
+                        color: attributes.colors[corner.position],
+                        ..Default::default()
+                    });
+                    vertices.len() as u32 - 1
+                });
+                indices.push(index);
+            }
+        }
+    }
+
+    generate_tangents(&mut vertices, &mut indices);
+
+    Mesh {
+        vertices,
+        indices,
+        material_idx: group.material_idx,
+        targets: Vec::new()
+    }
+}
+
+// Loads a Wavefront OBJ file and the MTL files it references. Faces are split into one mesh per group and
+// material, polygons are triangulated and missing normals are generated from the smoothing groups.
+pub fn load_obj_with_cache<P: AsRef<Path>>(file_path: P, texture_cache: &mut TextureCache) -> Result<Model, LoadError> {
+    let file_path = file_path.as_ref();
+    let source = read_source(file_path)?;
+    let directory = file_path.parent().unwrap_or(Path::new(""));
+
+    let mut attributes = Attributes {
+        positions: Vec::new(),
+        colors: Vec::new(),
+        tex_coords: Vec::new(),
+        normals: Vec::new()
+    };
+    // Faces before any usemtl, or using an unknown material, get the default one.
+    let mut materials = vec![default_material()];
+    let mut material_names: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<FaceGroup> = Vec::new();
+    let mut group_indices: HashMap<(String, usize), usize> = HashMap::new();
+
+    let mut group_name = String::new();
+    let mut material_idx = 0;
+    let mut smoothing_group = 0;
+    for (line, keyword, arguments) in statements(&source) {
+        match keyword {
+            "v" => {
+                let values = parse_floats(&arguments, 3, file_path, line)?;
+                attributes.positions.push(Vec3::new(values[0], values[1], values[2]));
+                attributes.colors.push(match values[3..] {
+                    [r, g, b, ..] => Vec4::new(r, g, b, 1.0),
+                    _ => Vec4::ONE
+                });
+            },
+            "vt" => {
+                let values = parse_floats(&arguments, 1, file_path, line)?;
+                // OBJ puts v = 0 at the bottom of the image.
+                attributes.tex_coords.push(Vec2::new(values[0], 1.0 - values.get(1).copied().unwrap_or(0.0)));
+            },
+            "vn" => {
+                let values = parse_floats(&arguments, 3, file_path, line)?;
+                attributes.normals.push(Vec3::new(values[0], values[1], values[2]));
+            },
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(LoadError::parse(file_path, line, format!("face with {} corners", arguments.len())));
+                }
+                let corners = arguments
+                    .iter()
+                    .map(|token| parse_corner(token, &attributes, file_path, line))
+                    .collect::<Result<Vec<Corner>, LoadError>>()?;
+
+                let group = *group_indices.entry((group_name.clone(), material_idx)).or_insert_with(|| {
+                    groups.push(FaceGroup {
+                        material_idx,
+                        faces: Vec::new()
+                    });
+                    groups.len() - 1
+                });
+                groups[group].faces.push(Face {
+                    corners,
+                    smoothing_group
+                });
+            },
+            "g" | "o" => group_name = arguments.join(" "),
+            "s" => {
+                smoothing_group = match arguments.first() {
+                    None | Some(&"off") => 0,
+                    Some(group) => group.parse().map_err(|_| LoadError::parse(file_path, line, format!("invalid smoothing group {:?}", group)))?
+                };
+            },
+            "mtllib" => {
+                for name in &arguments {
+                    load_mtl(&directory.join(name), texture_cache, &mut materials, &mut material_names)?;
+                }
+            },
+            "usemtl" => material_idx = material_names.get(&arguments.join(" ")).copied().unwrap_or(0),
+            // Points, lines, curves and surfaces aren't drawn.
+            _ => {}
+        }
+    }
+
+    let meshes = groups.iter().map(|group| build_mesh(group, &attributes)).collect();
+    Ok(Model::from_meshes(meshes, materials))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `files` to a fresh directory and loads the first one.
+    fn load(test_name: &str, files: &[(&str, &str)]) -> Result<Model, LoadError> {
+        let directory = std::env::temp_dir().join(format!("obj_{}_{}", test_name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for (name, contents) in files {
+            std::fs::write(directory.join(name), contents).unwrap();
+        }
+        let model = load_obj_with_cache(directory.join(files[0].0), &mut TextureCache::default());
+        std::fs::remove_dir_all(&directory).unwrap();
+        model
+    }
+
+    #[test]
+    fn ignores_missing_materials_and_textures() {
+        let model = load("missing", &[
+            ("model.obj", "mtllib missing.mtl found.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\nusemtl lost\nf 1 2 3\n"),
+            ("found.mtl", "newmtl red\nKd 1 0 0\nmap_Kd missing.png\nmap_Bump -bm 0.5 missing.png\n")
+        ]).unwrap();
+
+        assert_eq!(model.materials.len(), 2);
+        assert_eq!(model.materials[1].base_color, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        assert!(model.materials[1].base_color_texture.is_none());
+        assert!(model.materials[1].normal_texture.is_none());
+        assert_eq!(model.materials[1].normal_scale, 1.0);
+        let mut material_indices: Vec<usize> = model.meshes.iter().map(|mesh| mesh.material_idx).collect();
+        material_indices.sort();
+        assert_eq!(material_indices, [0, 1]);
+    }
+    #[test]
+    fn triangulates_concave_polygons() {
+        // An arrow head whose corner 1 is reflex, so a fan from corner 0 would leave the polygon.
+        let points = [Vec3::new(2.0, 1.0, 0.0), Vec3::new(0.0, 0.5, 0.0), Vec3::new(-2.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 0.0)];
+        let triangles = triangulate(&points);
+
+        assert_eq!(triangles.len(), 2);
+        assert!(triangles.iter().all(|triangle| triangle.contains(&1) && triangle.contains(&3)));
+        // Every triangle keeps the counter-clockwise winding and the areas add up to the polygon's.
+        let area = |[a, b, c]: [usize; 3]| (points[b] - points[a]).cross(points[c] - points[a]).z / 2.0;
+        assert!(triangles.iter().all(|triangle| area(*triangle) > 0.0));
+        assert_eq!(triangles.iter().map(|triangle| area(*triangle)).sum::<f32>(), 1.0);
+    }
+
+    #[test]
+    fn resolves_relative_indices() {
+        let model = load("relative", &[("model.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\nf 4 -3 -2\n")]).unwrap();
+
+        let positions: Vec<Vec3> = model.meshes[0].indices.iter().map(|i| model.meshes[0].vertices[*i as usize].position).collect();
+        assert_eq!(positions, [
+            Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(5.0, 5.0, 5.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)
+        ]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        for (face, index) in [("f 1 2 4", "4"), ("f 1 2 0", "0"), ("f -4 1 2", "-4"), ("f 1/2 2 3", "2")] {
+            match load("out_of_range", &[("model.obj", &format!("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\n{}\n", face))]) {
+                Err(LoadError::Parse { line, reason, .. }) => {
+                    assert_eq!(line, 5);
+                    assert_eq!(reason, format!("index {} is out of range", index));
+                },
+                Err(err) => panic!("unexpected error {}", err),
+                Ok(_) => panic!("{:?} loaded", face)
+            }
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use glam::*;
+use std::path::Path;
+use crate::{Model, Mesh, Vertex, LoadError, default_material, NormalGeneration, generate_normals, triangulate};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(ScalarType::I8),
+            "uchar" | "uint8" => Some(ScalarType::U8),
+            "short" | "int16" => Some(ScalarType::I16),
+            "ushort" | "uint16" => Some(ScalarType::U16),
+            "int" | "int32" => Some(ScalarType::I32),
+            "uint" | "uint32" => Some(ScalarType::U32),
+            "float" | "float32" => Some(ScalarType::F32),
+            "double" | "float64" => Some(ScalarType::F64),
+            _ => None
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8
+        }
+    }
+
+    // Value a color component of this type has at full intensity.
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::I8 => i8::MAX as f64,
+            ScalarType::U8 => u8::MAX as f64,
+            ScalarType::I16 => i16::MAX as f64,
+            ScalarType::U16 => u16::MAX as f64,
+            ScalarType::I32 => i32::MAX as f64,
+            ScalarType::U32 => u32::MAX as f64,
+            ScalarType::F32 | ScalarType::F64 => 1.0
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PropertyType {
+    Scalar(ScalarType),
+    // A count followed by that many items.
+    List { count: ScalarType, item: ScalarType }
+}
+
+struct Property {
+    name: String,
+    property_type: PropertyType
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+    // Header line declaring the element.
+    line: usize
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>
+}
+
+// Parses the header and returns it with its line count and the offset of the data following it.
+fn parse_header(bytes: &[u8], file_path: &Path) -> Result<(Header, usize, usize), LoadError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+    let mut line_number = 0;
+    loop {
+        let Some(length) = bytes[offset..].iter().position(|byte| *byte == b'\n') else {
+            return Err(LoadError::parse(file_path, line_number, "header without end_header".to_string()));
+        };
+        let line = String::from_utf8_lossy(&bytes[offset..offset + length]);
+        offset += length + 1;
+        line_number += 1;
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(LoadError::parse(file_path, line_number, "not a PLY file".to_string()));
+            }
+            continue;
+        }
+
+        let scalar_type = |name: &str| ScalarType::parse(name).ok_or_else(|| LoadError::parse(file_path, line_number, format!("unknown type {:?}", name)));
+        match tokens.as_slice() {
+            ["format", "ascii", ..] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", ..] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", ..] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| LoadError::parse(file_path, line_number, format!("invalid element count {:?}", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                    line: line_number
+                });
+            },
+            ["property", "list", count, item, name] => {
+                let property_type = PropertyType::List { count: scalar_type(count)?, item: scalar_type(item)? };
+                let element = elements.last_mut().ok_or_else(|| LoadError::parse(file_path, line_number, "property outside of an element".to_string()))?;
+                element.properties.push(Property { name: name.to_string(), property_type });
+            },
+            ["property", scalar, name] => {
+                let property_type = PropertyType::Scalar(scalar_type(scalar)?);
+                let element = elements.last_mut().ok_or_else(|| LoadError::parse(file_path, line_number, "property outside of an element".to_string()))?;
+                element.properties.push(Property { name: name.to_string(), property_type });
+            },
+            ["end_header"] => break,
+            [] | ["comment", ..] | ["obj_info", ..] => {},
+            _ => return Err(LoadError::parse(file_path, line_number, format!("unexpected header line {:?}", line.trim())))
+        }
+    }
+
+    let format = format.ok_or_else(|| LoadError::parse(file_path, line_number, "header without format".to_string()))?;
+    Ok((Header { format, elements }, line_number, offset))
+}
+
+// Reads the values following the header, as text or in either byte order.
+enum Body<'a> {
+    Ascii { lines: std::str::Lines<'a>, tokens: std::str::SplitAsciiWhitespace<'a>, line: usize },
+    Binary { bytes: &'a [u8], offset: usize, big_endian: bool }
+}
+
+impl Body<'_> {
+    // Line of the last value read, binary bodies have no lines and report 0.
+    fn line(&self) -> usize {
+        match self {
+            Body::Ascii { line, .. } => *line,
+            Body::Binary { .. } => 0
+        }
+    }
+
+    fn read(&mut self, scalar_type: ScalarType) -> Option<f64> {
+        match self {
+            Body::Ascii { lines, tokens, line } => loop {
+                if let Some(token) = tokens.next() {
+                    return token.parse().ok();
+                }
+                *tokens = lines.next()?.split_ascii_whitespace();
+                *line += 1;
+            },
+            Body::Binary { bytes, offset, big_endian } => {
+                let size = scalar_type.size();
+                let mut value = [0; 8];
+                value[..size].copy_from_slice(bytes.get(*offset..*offset + size)?);
+                *offset += size;
+                if *big_endian {
+                    value[..size].reverse();
+                }
+
+                let [b0, b1, b2, b3, ..] = value;
+                Some(match scalar_type {
+                    ScalarType::I8 => b0 as i8 as f64,
+                    ScalarType::U8 => b0 as f64,
+                    ScalarType::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(value)
+                })
+            }
+        }
+    }
+}
+
+// Loads an ASCII or binary PLY file, reading the positions, normals and colors of its vertices and the polygons
+// of its faces. Other elements, like edges, are skipped.
+pub fn load_ply<P: AsRef<Path>>(file_path: P, normal_generation: NormalGeneration) -> Result<Model, LoadError> {
+    let file_path = file_path.as_ref();
+    let bytes = std::fs::read(file_path).map_err(|source| LoadError::Io {
+        path: file_path.to_path_buf(),
+        source
+    })?;
+
+    let (header, header_lines, offset) = parse_header(&bytes, file_path)?;
+    let mut body = match header.format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(&bytes[offset..]).map_err(|err| LoadError::parse(file_path, 0, err.to_string()))?;
+            Body::Ascii {
+                lines: text.lines(),
+                tokens: "".split_ascii_whitespace(),
+                line: header_lines
+            }
+        },
+        format => Body::Binary {
+            bytes: &bytes[offset..],
+            offset: 0,
+            big_endian: format == Format::BinaryBigEndian
+        }
+    };
+
+    let mut vertices: Vec<Vertex> = Vec::new();
+    // Vertex indices of each face, with the line it was read from.
+    let mut polygons: Vec<(Vec<usize>, usize)> = Vec::new();
+    let mut has_normals = false;
+    for element in &header.elements {
+        let find = |names: &[&str]| element.properties.iter().position(|property| names.contains(&property.name.as_str()));
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let color = [find(&["red", "diffuse_red"]), find(&["green", "diffuse_green"]), find(&["blue", "diffuse_blue"]), find(&["alpha"])];
+        let vertex_indices = find(&["vertex_indices", "vertex_index"]);
+
+        match element.name.as_str() {
+            "vertex" if position.contains(&None) => {
+                return Err(LoadError::parse(file_path, element.line, "vertex element without x, y and z".to_string()));
+            },
+            "vertex" => has_normals = !normal.contains(&None),
+            "face" if vertex_indices.is_none() => {
+                return Err(LoadError::parse(file_path, element.line, "face element without vertex_indices".to_string()));
+            },
+            _ => {}
+        }
+
+        let mut scalars = vec![0.0; element.properties.len()];
+        let mut lists = vec![Vec::new(); element.properties.len()];
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                let result = match property.property_type {
+                    PropertyType::Scalar(scalar_type) => body.read(scalar_type).map(|value| scalars[i] = value),
+                    PropertyType::List { count, item } => body.read(count).and_then(|count| {
+                        lists[i].clear();
+                        (0..count as usize).try_for_each(|_| body.read(item).map(|value| lists[i].push(value)))
+                    })
+                };
+                result.ok_or_else(|| LoadError::parse(file_path, body.line(), format!("truncated or invalid {} data", element.name)))?;
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let value = |i: Option<usize>| i.map(|i| scalars[i] as f32);
+                    let color_value = |i: Option<usize>| i.map(|i| match element.properties[i].property_type {
+                        PropertyType::Scalar(scalar_type) => (scalars[i] / scalar_type.color_scale()) as f32,
+                        PropertyType::List { .. } => 1.0
+                    });
+                    vertices.push(Vertex {
+                        position: Vec3::from(position.map(|i| value(i).unwrap_or(0.0))),
+                        normal: Vec3::from(normal.map(|i| value(i).unwrap_or(0.0))),
+                        color: Vec4::from(color.map(|i| color_value(i).unwrap_or(1.0))),
+                        ..Default::default()
+                    });
+                },
+                "face" => {
+                    let indices = vertex_indices.map_or(&[][..], |i| &lists[i]);
+                    // Casting would turn negative indices into 0, so they are reported like indices past the end.
+                    if let Some(index) = indices.iter().find(|index| **index < 0.0) {
+                        return Err(LoadError::parse(file_path, body.line(), format!("face references missing vertex {}", index)));
+                    }
+                    polygons.push((indices.iter().map(|index| *index as usize).collect(), body.line()));
+                },
+                _ => {}
+            }
+        }
+    }
+
+    let mut indices = Vec::new();
+    for (polygon, line) in &polygons {
+        if let Some(index) = polygon.iter().find(|index| **index >= vertices.len()) {
+            return Err(LoadError::parse(file_path, *line, format!("face references missing vertex {}", index)));
+        }
+        if polygon.len() < 3 {
+            continue;
+        }
+        let points: Vec<Vec3> = polygon.iter().map(|index| vertices[*index].position).collect();
+        for triangle in triangulate(&points) {
+            indices.extend(triangle.map(|corner| polygon[corner] as u32));
+        }
+    }
+    if !has_normals {
+        generate_normals(&mut vertices, &mut indices, normal_generation);
+    }
+
+    let mesh = Mesh {
+        vertices,
+        indices,
+        material_idx: 0,
+        targets: Vec::new()
+    };
+    Ok(Model::from_meshes(vec![mesh], vec![default_material()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [Vec3; 4] = [Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0), Vec3::Y];
+
+    fn header(format: &str) -> Vec<u8> {
+        format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        ).into_bytes()
+    }
+
+    // A quad of POSITIONS in the given byte order.
+    fn binary(format: &str, to_bytes: fn(u32) -> [u8; 4]) -> Vec<u8> {
+        let mut bytes = header(format);
+        for position in POSITIONS {
+            bytes.extend(position.to_array().iter().flat_map(|value| to_bytes(value.to_bits())));
+        }
+        bytes.push(4);
+        bytes.extend([0, 1, 2, 3].iter().flat_map(|index| to_bytes(*index)));
+        bytes
+    }
+
+    fn load(test_name: &str, bytes: &[u8]) -> Result<Model, LoadError> {
+        let file_path = std::env::temp_dir().join(format!("ply_{}_{}.ply", test_name, std::process::id()));
+        std::fs::write(&file_path, bytes).unwrap();
+        let model = load_ply(&file_path, NormalGeneration::Flat);
+        std::fs::remove_file(&file_path).unwrap();
+        model
+    }
+
+    fn error(test_name: &str, bytes: &[u8]) -> (usize, String) {
+        match load(test_name, bytes) {
+            Err(LoadError::Parse { line, reason, .. }) => (line, reason),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("malformed file loaded")
+        }
+    }
+
+    fn assert_quad(model: &Model) {
+        let mesh = &model.meshes[0];
+        let positions: Vec<Vec3> = mesh.indices.iter().map(|i| mesh.vertices[*i as usize].position).collect();
+        assert_eq!(positions.len(), 6);
+        assert!(POSITIONS.iter().all(|position| positions.contains(position)));
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == Vec3::Z));
+    }
+
+    #[test]
+    fn loads_ascii_files() {
+        let mut bytes = header("ascii");
+        bytes.extend(b"0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n");
+        assert_quad(&load("ascii", &bytes).unwrap());
+    }
+
+    #[test]
+    fn loads_binary_files() {
+        assert_quad(&load("little_endian", &binary("binary_little_endian", u32::to_le_bytes)).unwrap());
+        assert_quad(&load("big_endian", &binary("binary_big_endian", u32::to_be_bytes)).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_bodies() {
+        let bytes = binary("binary_little_endian", u32::to_le_bytes);
+        assert_eq!(error("truncated_binary", &bytes[..bytes.len() - 2]), (0, "truncated or invalid face data".to_string()));
+
+        let mut bytes = header("ascii");
+        bytes.extend(b"0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2\n");
+        assert_eq!(error("truncated_ascii", &bytes), (14, "truncated or invalid face data".to_string()));
+    }
+
+    #[test]
+    fn rejects_negative_indices() {
+        let mut bytes = header("ascii");
+        bytes.extend(b"0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 -2 3\n");
+        assert_eq!(error("negative", &bytes), (14, "face references missing vertex -2".to_string()));
+    }
+}
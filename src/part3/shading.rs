@@ -0,0 +1,97 @@
+use glam::*;
+use std::f32::consts::PI;
+use crate::ibl::distribution_ggx;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadingModel {
+    Lambert,
+    CookTorrance
+}
+
+// Material inputs at a point on a surface, including the layers added by glTF material extensions.
+#[derive(Clone, Copy, Debug)]
+pub struct Surface {
+    pub base_color: Vec3,
+    pub metallic: f32,
+    pub roughness: f32,
+    // Fraction of the diffuse lobe replaced by light passing through the surface.
+    pub transmission: f32,
+    // Strength, roughness and normal of the clearcoat layer.
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub clearcoat_normal: Vec3,
+    pub sheen_color: Vec3,
+    pub sheen_roughness: f32
+}
+
+// Height correlated Smith visibility term, which already includes the 1 / (4 n.l n.v) denominator.
+fn visibility_smith_ggx(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let ggx_v = n_dot_l * (n_dot_v * n_dot_v * (1.0 - a2) + a2).sqrt();
+    let ggx_l = n_dot_v * (n_dot_l * n_dot_l * (1.0 - a2) + a2).sqrt();
+    let ggx = ggx_v + ggx_l;
+    if ggx > 0.0 { 0.5 / ggx } else { 0.0 }
+}
+
+pub fn fresnel_schlick(f0: Vec3, v_dot_h: f32) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1.0 - v_dot_h).clamp(0.0, 1.0).powi(5)
+}
+
+// "Charlie" sheen distribution by Estevez and Kulla.
+fn distribution_charlie(n_dot_h: f32, roughness: f32) -> f32 {
+    let inv_alpha = 1.0 / (roughness * roughness).max(1e-3);
+    let sin2 = (1.0 - n_dot_h * n_dot_h).max(0.0);
+    (2.0 + inv_alpha) * sin2.powf(inv_alpha * 0.5) / (2.0 * PI)
+}
+
+// Neubelt and Pettineo's visibility term for sheen, already divided by 4 n.l n.v.
+fn visibility_neubelt(n_dot_v: f32, n_dot_l: f32) -> f32 {
+    1.0 / (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v)).max(1e-4)
+}
+
+// Specular reflection of the clearcoat layer and the fraction of light it reflects, which doesn't reach the layers below.
+fn clearcoat(n: &Vec3, v: &Vec3, l: &Vec3, roughness: f32) -> (f32, f32) {
+    let h = (*v + *l).normalize_or_zero();
+    let n_dot_l = n.dot(*l);
+    let fresnel = fresnel_schlick(Vec3::splat(0.04), v.dot(h).max(0.0)).x;
+    if n_dot_l <= 0.0 {
+        return (0.0, fresnel);
+    }
+
+    let n_dot_v = n.dot(*v).abs().max(1e-4);
+    let specular = fresnel * visibility_smith_ggx(n_dot_v, n_dot_l, roughness) * distribution_ggx(n.dot(h).max(0.0), roughness);
+    (specular * n_dot_l, fresnel)
+}
+
+// Radiance reflected towards `v` from a light arriving along `l` with the given radiance, using the
+// metallic-roughness BRDF of the glTF specification (GGX distribution, Smith visibility, Schlick fresnel)
+// with the sheen and clearcoat layers on top.
+pub fn cook_torrance(n: &Vec3, v: &Vec3, l: &Vec3, radiance: &Vec3, surface: &Surface) -> Vec3 {
+    let n_dot_l = n.dot(*l);
+    if n_dot_l <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let h = (*v + *l).normalize_or_zero();
+    let n_dot_v = n.dot(*v).abs().max(1e-4);
+    let n_dot_h = n.dot(h).max(0.0);
+    let v_dot_h = v.dot(h).max(0.0);
+
+    let f0 = Vec3::splat(0.04).lerp(surface.base_color, surface.metallic);
+    let fresnel = fresnel_schlick(f0, v_dot_h);
+
+    // Transmitted light leaves through the back of the surface, so it is not reflected towards the viewer.
+    let diffuse_color = surface.base_color.lerp(Vec3::ZERO, surface.metallic) * (1.0 - surface.transmission);
+    let diffuse = (Vec3::ONE - fresnel) * diffuse_color / PI;
+    let specular = fresnel * visibility_smith_ggx(n_dot_v, n_dot_l, surface.roughness) * distribution_ggx(n_dot_h, surface.roughness);
+    let sheen = surface.sheen_color * distribution_charlie(n_dot_h, surface.sheen_roughness) * visibility_neubelt(n_dot_v, n_dot_l);
+
+    let mut color = (diffuse + specular + sheen) * n_dot_l;
+    if surface.clearcoat > 0.0 {
+        let (clearcoat_specular, clearcoat_fresnel) = clearcoat(&surface.clearcoat_normal, v, l, surface.clearcoat_roughness);
+        color = color * (1.0 - surface.clearcoat * clearcoat_fresnel) + Vec3::splat(surface.clearcoat * clearcoat_specular);
+    }
+
+    color * *radiance
+}
@@ -0,0 +1,339 @@
+use glam::*;
+use crate::{Camera, Framebuffer, Light, LightKind, MeshInstance, CascadedShadowMap, CubeShadowMap, draw_model_depth, clip_to_screen_space};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadowFilter {
+    // A single depth comparison, giving aliased edges.
+    Hard,
+    // Percentage-closer filtering over a square of `kernel_size` by `kernel_size` texels.
+    Pcf { kernel_size: usize },
+    // Percentage-closer filtering over a randomly rotated Poisson disc of `radius` texels.
+    Poisson { radius: f32 },
+    // Percentage-closer soft shadows, widening the penumbra with the distance to the blocker.
+    // `light_size` is the radius of the light in world units for spot lights, and the tangent
+    // of its angular radius for directional lights.
+    Pcss { light_size: f32 }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    // Width and height of the shadow map in texels.
+    pub resolution: usize,
+    // Constant offset pushed into the shadow map depths, in normalized depth units.
+    pub depth_bias: f32,
+    // Offset scaled by how steep each triangle is in the shadow map, in texels.
+    pub slope_bias: f32,
+    pub filter: ShadowFilter,
+    // Number of maps directional lights split the view frustum into, 1 fits a single map to the scene.
+    pub cascade_count: usize,
+    // Blend between uniform (0) and logarithmic (1) spacing of the cascade splits.
+    pub cascade_split_lambda: f32,
+    // Fraction at the end of each cascade over which it fades into the next one.
+    pub cascade_blend: f32
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            resolution: 1024,
+            depth_bias: 0.0005,
+            slope_bias: 1.5,
+            filter: ShadowFilter::Hard,
+            cascade_count: 1,
+            cascade_split_lambda: 0.5,
+            cascade_blend: 0.1
+        }
+    }
+}
+
+pub const POISSON_DISC: [Vec2; 16] = [
+    Vec2::new(-0.942_016_24, -0.399_062_16),
+    Vec2::new(0.945_586_1, -0.768_907_25),
+    Vec2::new(-0.094_184_1, -0.929_388_7),
+    Vec2::new(0.344_959_38, 0.293_877_6),
+    Vec2::new(-0.915_885_8, 0.457_714_32),
+    Vec2::new(-0.815_442_3, -0.879_124_64),
+    Vec2::new(-0.382_775_43, 0.276_768_45),
+    Vec2::new(0.974_844, 0.756_483_8),
+    Vec2::new(0.443_233_25, -0.975_115_54),
+    Vec2::new(0.537_429_8, -0.473_734_2),
+    Vec2::new(-0.264_969_1, -0.418_930_23),
+    Vec2::new(0.791_975_14, 0.190_901_88),
+    Vec2::new(-0.241_888_4, 0.997_065_07),
+    Vec2::new(-0.814_099_55, 0.914_375_9),
+    Vec2::new(0.199_841_26, 0.786_413_7),
+    Vec2::new(0.143_831_61, -0.141_007_9)
+];
+
+// Largest radius in texels searched for blockers by PCSS.
+pub(crate) const MAX_BLOCKER_SEARCH_RADIUS: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ShadowProjection {
+    // `size` is the width of the covered area in world units.
+    Orthographic { near: f32, far: f32, size: f32 },
+    Perspective { near: f32, far: f32, fov: f32 }
+}
+
+impl ShadowProjection {
+    fn matrix(&self) -> Mat4 {
+        match *self {
+            ShadowProjection::Orthographic { near, far, size } => {
+                Mat4::orthographic_rh(-size * 0.5, size * 0.5, -size * 0.5, size * 0.5, near, far)
+            },
+            ShadowProjection::Perspective { near, far, fov } => Mat4::perspective_rh(fov, 1.0, near, far)
+        }
+    }
+
+    // Distance from the light for a depth stored in the shadow map.
+    fn linear_depth(&self, depth: f32) -> f32 {
+        match *self {
+            ShadowProjection::Orthographic { near, far, .. } => near + depth * (far - near),
+            ShadowProjection::Perspective { near, far, .. } => near * far / (far - depth * (far - near))
+        }
+    }
+
+    // Width in world units covered by the shadow map at the given distance from the light.
+    fn width_at(&self, distance: f32) -> f32 {
+        match *self {
+            ShadowProjection::Orthographic { size, .. } => size,
+            ShadowProjection::Perspective { fov, .. } => 2.0 * distance * (fov * 0.5).tan()
+        }
+    }
+
+    // Width of the penumbra in world units for a light of the given size.
+    fn penumbra_width(&self, light_size: f32, receiver: f32, blocker: f32) -> f32 {
+        match self {
+            ShadowProjection::Orthographic { .. } => light_size * (receiver - blocker),
+            ShadowProjection::Perspective { .. } => light_size * (receiver - blocker) / blocker
+        }
+    }
+}
+
+pub struct ShadowMap {
+    depth: Framebuffer,
+    view_proj: Mat4,
+    projection: ShadowProjection,
+    filter: ShadowFilter
+}
+
+// The shadow map(s) rendered for one light.
+pub enum LightShadow {
+    Single(ShadowMap),
+    Cascaded(CascadedShadowMap),
+    Cube(Box<CubeShadowMap>)
+}
+
+impl LightShadow {
+    // Renders the shadow maps `settings` asks for, returns `None` if the light can't cast shadows.
+    pub fn render(
+        light: &Light,
+        settings: &ShadowSettings,
+        instances: &[MeshInstance],
+        model_matrix: &Mat4,
+        bounds: (Vec3, f32),
+        camera: &Camera
+    ) -> Option<Self> {
+        if light.kind == LightKind::Directional && settings.cascade_count > 1 {
+            let cascaded = CascadedShadowMap::render(light, settings, instances, model_matrix, bounds, camera);
+            return Some(LightShadow::Cascaded(cascaded));
+        }
+
+        if light.kind == LightKind::Point {
+            return Some(LightShadow::Cube(Box::new(CubeShadowMap::render(light, settings, instances, model_matrix, bounds))));
+        }
+
+        ShadowMap::render(light, settings, instances, model_matrix, bounds).map(LightShadow::Single)
+    }
+
+    pub fn visibility(&self, world_position: &Vec3) -> f32 {
+        match self {
+            LightShadow::Single(shadow_map) => shadow_map.visibility(world_position),
+            LightShadow::Cascaded(cascaded) => cascaded.visibility(world_position),
+            LightShadow::Cube(cube) => cube.visibility(world_position)
+        }
+    }
+
+    // Index of the cascade used at `world_position`, for debugging.
+    pub fn cascade(&self, world_position: &Vec3) -> Option<usize> {
+        match self {
+            LightShadow::Single(_) | LightShadow::Cube(_) => None,
+            LightShadow::Cascaded(cascaded) => cascaded.cascade(world_position)
+        }
+    }
+}
+
+// Cheap per pixel pseudo random angle used to rotate the Poisson disc.
+pub fn random_angle(p: Vec2) -> f32 {
+    let hash = (p.dot(Vec2::new(12.9898, 78.233)).sin() * 43758.547).fract();
+    hash * std::f32::consts::TAU
+}
+
+// Center and radius of a sphere enclosing all vertices of `instances`, in model space.
+pub fn bounding_sphere(instances: &[MeshInstance]) -> (Vec3, f32) {
+    let positions = instances.iter().flat_map(|instance| {
+        instance.vertices.iter().map(|vertex| instance.transform.transform_point3(vertex.position))
+    });
+
+    let (min, max) = positions.clone().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), position| (min.min(position), max.max(position))
+    );
+    if min.x > max.x {
+        return (Vec3::ZERO, 0.0);
+    }
+
+    let center = (min + max) * 0.5;
+    let radius = positions.map(|position| position.distance(center)).fold(0.0, f32::max);
+    (center, radius)
+}
+
+pub fn look_at(eye: Vec3, direction: Vec3) -> Mat4 {
+    let up = if direction.y.abs() < 0.99 { Vec3::Y } else { Vec3::X };
+    Mat4::look_at_rh(eye, eye + direction, up)
+}
+
+impl ShadowMap {
+    // Renders the depth of `instances` as seen from `light`, covering the world space sphere `bounds`.
+    // Returns `None` for lights that can't be covered by a single map.
+    pub fn render(
+        light: &Light,
+        settings: &ShadowSettings,
+        instances: &[MeshInstance],
+        model_matrix: &Mat4,
+        bounds: (Vec3, f32)
+    ) -> Option<Self> {
+        let (center, radius) = bounds;
+        let radius = radius.max(1e-3);
+
+        let (view, projection) = match light.kind {
+            LightKind::Directional => {
+                let view = look_at(center - light.direction * 2.0 * radius, light.direction);
+                (view, ShadowProjection::Orthographic { near: radius, far: 3.0 * radius, size: 2.0 * radius })
+            },
+            LightKind::Spot { outer_cone_angle, .. } => {
+                let distance = light.position.distance(center);
+                let far = light.range.unwrap_or(distance + radius).max(1e-2);
+                let near = (distance - radius).max(far * 1e-3);
+                let view = look_at(light.position, light.direction);
+                (view, ShadowProjection::Perspective { near, far, fov: 2.0 * outer_cone_angle })
+            },
+            LightKind::Point => return None
+        };
+
+        Some(ShadowMap::render_view(&view, projection, settings, instances, model_matrix))
+    }
+
+    // Renders the depth of `instances` through the given light view and projection.
+    pub fn render_view(
+        view: &Mat4,
+        projection: ShadowProjection,
+        settings: &ShadowSettings,
+        instances: &[MeshInstance],
+        model_matrix: &Mat4
+    ) -> Self {
+        let view_proj = projection.matrix() * *view;
+
+        let mut depth = Framebuffer::new(settings.resolution, settings.resolution);
+        depth.clear(u32::MAX);
+        draw_model_depth(&mut depth, instances, &(view_proj * *model_matrix), settings.depth_bias, settings.slope_bias);
+
+        ShadowMap {
+            depth,
+            view_proj,
+            projection,
+            filter: settings.filter
+        }
+    }
+
+    // Fraction of the light reaching `world_position`, 0 when it is fully shadowed.
+    pub fn visibility(&self, world_position: &Vec3) -> f32 {
+        let clip_space = self.view_proj * Vec4::from((*world_position, 1.0));
+        if clip_space.w <= 0.0 {
+            return 1.0;
+        }
+
+        let ndc = clip_space.xyz() / clip_space.w;
+        let size = Vec2::new(self.depth.width() as f32, self.depth.height() as f32);
+        let p = clip_to_screen_space(&ndc.xy(), &size);
+        if ndc.z > 1.0 || p.x < 0.0 || p.y < 0.0 || p.x >= size.x || p.y >= size.y {
+            return 1.0;
+        }
+
+        match self.filter {
+            ShadowFilter::Hard => self.compare(p, ndc.z),
+            ShadowFilter::Pcf { kernel_size } => {
+                let half = (kernel_size.max(1) - 1) as f32 * 0.5;
+                let mut lit = 0.0;
+                for y in 0..kernel_size.max(1) {
+                    for x in 0..kernel_size.max(1) {
+                        lit += self.compare(p + Vec2::new(x as f32 - half, y as f32 - half), ndc.z);
+                    }
+                }
+                lit / (kernel_size.max(1) * kernel_size.max(1)) as f32
+            },
+            ShadowFilter::Poisson { radius } => self.poisson(p, ndc.z, radius),
+            ShadowFilter::Pcss { light_size } => {
+                let receiver = self.projection.linear_depth(ndc.z);
+                let texels_per_unit = size.x / self.projection.width_at(receiver);
+
+                // Average the depth of everything between the receiver and the light that could cast the penumbra.
+                let near = self.projection.linear_depth(0.0);
+                let search_radius = (self.projection.penumbra_width(light_size, receiver, near) * texels_per_unit)
+                    .clamp(1.0, MAX_BLOCKER_SEARCH_RADIUS);
+                let rotation = Vec2::from_angle(random_angle(p));
+
+                let mut blocker_depth = 0.0;
+                let mut blocker_count = 0;
+                for offset in POISSON_DISC {
+                    let q = p + rotation.rotate(offset) * search_radius;
+                    if let Some(depth) = self.depth_at(q) {
+                        if depth < ndc.z {
+                            blocker_depth += self.projection.linear_depth(depth);
+                            blocker_count += 1;
+                        }
+                    }
+                }
+                if blocker_count == 0 {
+                    return 1.0;
+                }
+
+                let blocker = blocker_depth / blocker_count as f32;
+                let penumbra = self.projection.penumbra_width(light_size, receiver, blocker) * texels_per_unit;
+                self.poisson(p, ndc.z, penumbra.max(1.0))
+            }
+        }
+    }
+
+    // Distance from the light to the nearest occluder along its view axis, towards `world_position`.
+    pub fn occluder_distance(&self, world_position: &Vec3) -> Option<f32> {
+        let ndc = self.view_proj.project_point3(*world_position);
+        let size = Vec2::new(self.depth.width() as f32, self.depth.height() as f32);
+        let depth = self.depth_at(clip_to_screen_space(&ndc.xy(), &size).min(size - 1.0))?;
+        Some(self.projection.linear_depth(depth))
+    }
+
+    fn depth_at(&self, p: Vec2) -> Option<f32> {
+        if p.x < 0.0 || p.y < 0.0 || p.x >= self.depth.width() as f32 || p.y >= self.depth.height() as f32 {
+            return None;
+        }
+        Some(self.depth.get_pixel_f32(p.x as usize, p.y as usize))
+    }
+
+    // 1 if the texel at `p` doesn't occlude `depth`, texels outside the map never do.
+    fn compare(&self, p: Vec2, depth: f32) -> f32 {
+        match self.depth_at(p) {
+            Some(occluder) if depth > occluder => 0.0,
+            _ => 1.0
+        }
+    }
+
+    fn poisson(&self, p: Vec2, depth: f32, radius: f32) -> f32 {
+        let rotation = Vec2::from_angle(random_angle(p));
+        let lit: f32 = POISSON_DISC
+            .iter()
+            .map(|offset| self.compare(p + rotation.rotate(*offset) * radius, depth))
+            .sum();
+        lit / POISSON_DISC.len() as f32
+    }
+}
@@ -0,0 +1,97 @@
+use glam::*;
+use crate::Vertex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkinningMethod {
+    LinearBlend,
+    // Blends the joint transforms as dual quaternions, which keeps the volume around twisting joints.
+    // Only the rotation and translation of the joints are used, their scale is ignored.
+    DualQuaternion
+}
+
+fn joint_matrix(joint_matrices: &[Mat4], joint: u16) -> Mat4 {
+    joint_matrices.get(joint as usize).copied().unwrap_or(Mat4::IDENTITY)
+}
+
+// Weights of `vertex` normalized to sum to one, `None` if it isn't influenced by any joint.
+fn normalized_weights(vertex: &Vertex) -> Option<Vec4> {
+    let sum = vertex.weights.dot(Vec4::ONE);
+    if sum > 0.0 { Some(vertex.weights / sum) } else { None }
+}
+
+// A rigid transform as a dual quaternion, rotating by `real` and then translating.
+#[derive(Clone, Copy)]
+struct DualQuat {
+    real: Quat,
+    dual: Quat
+}
+
+impl DualQuat {
+    fn from_mat4(matrix: &Mat4) -> Self {
+        let (_, rotation, translation) = matrix.to_scale_rotation_translation();
+        DualQuat {
+            real: rotation,
+            dual: Quat::from_vec4(Vec4::from((translation, 0.0))) * rotation * 0.5
+        }
+    }
+
+    fn translation(&self) -> Vec3 {
+        (self.dual * self.real.conjugate()).xyz() * 2.0
+    }
+}
+
+fn skin_linear_blend(vertex: &Vertex, weights: Vec4, joint_matrices: &[Mat4]) -> Vertex {
+    let matrix = (0..4).fold(Mat4::ZERO, |sum, i| sum + joint_matrix(joint_matrices, vertex.joints[i]) * weights[i]);
+    let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+
+    Vertex {
+        position: matrix.transform_point3(vertex.position),
+        normal: (normal_matrix * vertex.normal).normalize_or_zero(),
+        tangent: Vec4::from((matrix.transform_vector3(vertex.tangent.xyz()).normalize_or_zero(), vertex.tangent.w)),
+        ..*vertex
+    }
+}
+
+fn skin_dual_quaternion(vertex: &Vertex, weights: Vec4, joint_matrices: &[Mat4]) -> Vertex {
+    let first = DualQuat::from_mat4(&joint_matrix(joint_matrices, vertex.joints[0]));
+    let mut real = Vec4::ZERO;
+    let mut dual = Vec4::ZERO;
+    for i in 0..4 {
+        let joint = DualQuat::from_mat4(&joint_matrix(joint_matrices, vertex.joints[i]));
+        // q and -q are the same rotation, blend along the shortest path from the first joint.
+        let weight = if joint.real.dot(first.real) < 0.0 { -weights[i] } else { weights[i] };
+        real += Vec4::from(joint.real) * weight;
+        dual += Vec4::from(joint.dual) * weight;
+    }
+
+    let length = real.length();
+    if length <= 0.0 {
+        return *vertex;
+    }
+    let blended = DualQuat {
+        real: Quat::from_vec4(real / length),
+        dual: Quat::from_vec4(dual / length)
+    };
+
+    Vertex {
+        position: blended.real * vertex.position + blended.translation(),
+        normal: (blended.real * vertex.normal).normalize_or_zero(),
+        tangent: Vec4::from((blended.real * vertex.tangent.xyz(), vertex.tangent.w)),
+        ..*vertex
+    }
+}
+
+// Moves `vertices` by the joints influencing them. `joint_matrices` take each joint from the bind pose to its
+// current pose in model space, indexed by the vertices' `joints`.
+pub fn skin_vertices(vertices: &[Vertex], joint_matrices: &[Mat4], method: SkinningMethod) -> Vec<Vertex> {
+    vertices
+        .iter()
+        .map(|vertex| match normalized_weights(vertex) {
+            Some(weights) => match method {
+                SkinningMethod::LinearBlend => skin_linear_blend(vertex, weights, joint_matrices),
+                SkinningMethod::DualQuaternion => skin_dual_quaternion(vertex, weights, joint_matrices)
+            },
+            None => *vertex
+        })
+        .collect()
+}
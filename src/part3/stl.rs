@@ -0,0 +1,201 @@
+use glam::*;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::{Model, Mesh, Vertex, LoadError, default_material, NormalGeneration, generate_normals, triangle_normal, position_key};
+
+// A triangle with the normal stored for it in the file, which is zero in many exporters.
+struct Facet {
+    normal: Vec3,
+    positions: [Vec3; 3]
+}
+
+// Binary files start with an 80 byte header, which may begin with "solid" just like an ASCII file,
+// so the size the triangle count implies is checked first.
+fn is_binary(bytes: &[u8]) -> bool {
+    if let Some(count) = bytes.get(80..84) {
+        let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+        if count.checked_mul(50).and_then(|size| size.checked_add(84)) == Some(bytes.len()) {
+            return true;
+        }
+    }
+    !bytes.trim_ascii_start().starts_with(b"solid")
+}
+
+fn parse_binary(bytes: &[u8], file_path: &Path) -> Result<Vec<Facet>, LoadError> {
+    let count = bytes.get(80..84).map_or(0, |count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+    if bytes.len() < 84 || (bytes.len() - 84) / 50 < count {
+        return Err(LoadError::parse(file_path, 0, format!("truncated file, expected {} triangles", count)));
+    }
+
+    let read_vec3 = |offset: usize| {
+        let component = |i: usize| f32::from_le_bytes(bytes[offset + i * 4..offset + i * 4 + 4].try_into().unwrap());
+        Vec3::new(component(0), component(1), component(2))
+    };
+    // Each triangle is a normal, three positions and a 2 byte attribute that is ignored.
+    Ok((0..count)
+        .map(|i| {
+            let offset = 84 + i * 50;
+            Facet {
+                normal: read_vec3(offset),
+                positions: [read_vec3(offset + 12), read_vec3(offset + 24), read_vec3(offset + 36)]
+            }
+        })
+        .collect())
+}
+
+fn parse_ascii(source: &str, file_path: &Path) -> Result<Vec<Facet>, LoadError> {
+    let mut facets = Vec::new();
+    let mut normal = Vec3::ZERO;
+    let mut positions = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let vector = |values: &[&str]| match values {
+            [x, y, z, ..] => match (x.parse(), y.parse(), z.parse()) {
+                (Ok(x), Ok(y), Ok(z)) => Ok(Vec3::new(x, y, z)),
+                _ => Err(LoadError::parse(file_path, i + 1, format!("invalid vector {:?}", values)))
+            },
+            _ => Err(LoadError::parse(file_path, i + 1, format!("expected 3 numbers, found {}", values.len())))
+        };
+
+        match tokens.as_slice() {
+            ["facet", "normal", values @ ..] => {
+                normal = vector(values)?;
+                positions.clear();
+            },
+            ["vertex", values @ ..] => positions.push(vector(values)?),
+            ["endfacet", ..] => {
+                let &[a, b, c] = positions.as_slice() else {
+                    return Err(LoadError::parse(file_path, i + 1, format!("facet with {} vertices", positions.len())));
+                };
+                facets.push(Facet {
+                    normal,
+                    positions: [a, b, c]
+                });
+            },
+            // solid, outer loop, endloop and endsolid only give the facets structure.
+            _ => {}
+        }
+    }
+
+    Ok(facets)
+}
+
+// Merges vertices with the same position and normal, turning the separate triangles of an STL file into
+// an indexed mesh.
+fn weld(vertices: Vec<Vertex>) -> (Vec<Vertex>, Vec<u32>) {
+    let mut unique_vertices: HashMap<[u32; 6], u32> = HashMap::new();
+    let mut welded = Vec::new();
+    let indices = vertices
+        .into_iter()
+        .map(|vertex| {
+            let [x, y, z] = position_key(vertex.position);
+            let [nx, ny, nz] = position_key(vertex.normal);
+            *unique_vertices.entry([x, y, z, nx, ny, nz]).or_insert_with(|| {
+                welded.push(vertex);
+                welded.len() as u32 - 1
+            })
+        })
+        .collect();
+    (welded, indices)
+}
+
+// Loads an ASCII or binary STL file. Flat normals are taken from the facets where the file stores them.
+pub fn load_stl<P: AsRef<Path>>(file_path: P, normal_generation: NormalGeneration) -> Result<Model, LoadError> {
+    let file_path = file_path.as_ref();
+    let bytes = std::fs::read(file_path).map_err(|source| LoadError::Io {
+        path: file_path.to_path_buf(),
+        source
+    })?;
+
+    let facets = if is_binary(&bytes) {
+        parse_binary(&bytes, file_path)?
+    } else {
+        let source = std::str::from_utf8(&bytes).map_err(|err| LoadError::parse(file_path, 0, err.to_string()))?;
+        parse_ascii(source, file_path)?
+    };
+
+    let mut vertices: Vec<Vertex> = facets
+        .iter()
+        .flat_map(|facet| {
+            let [a, b, c] = facet.positions;
+            let normal = facet.normal.try_normalize().unwrap_or_else(|| triangle_normal(a, b, c));
+            facet.positions.map(|position| Vertex {
+                position,
+                normal,
+                ..Default::default()
+            })
+        })
+        .collect();
+    if normal_generation == NormalGeneration::Smooth {
+        let mut indices: Vec<u32> = (0..vertices.len() as u32).collect();
+        generate_normals(&mut vertices, &mut indices, normal_generation);
+    }
+    let (vertices, indices) = weld(vertices);
+
+    let mesh = Mesh {
+        vertices,
+        indices,
+        material_idx: 0,
+        targets: Vec::new()
+    };
+    Ok(Model::from_meshes(vec![mesh], vec![default_material()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_binary_files_with_a_solid_header() {
+        let mut bytes = b"solid exported by a binary writer".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend(1u32.to_le_bytes());
+        for value in [0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend([0, 0]);
+
+        assert!(is_binary(&bytes));
+        let facets = parse_binary(&bytes, Path::new("test.stl")).unwrap();
+        assert_eq!(facets.len(), 1);
+        assert_eq!(facets[0].normal, Vec3::Z);
+        assert_eq!(facets[0].positions, [Vec3::ZERO, Vec3::X, Vec3::Y]);
+    }
+
+    #[test]
+    fn parses_ascii_files() {
+        let source = "solid triangle\n  facet normal 0 0 1\n    outer loop\n      vertex 0 0 0\n      vertex 1 0 0\n      vertex 0 1 0\n    endloop\n  endfacet\nendsolid triangle\n";
+
+        assert!(!is_binary(source.as_bytes()));
+        let facets = parse_ascii(source, Path::new("test.stl")).unwrap();
+        assert_eq!(facets.len(), 1);
+        assert_eq!(facets[0].normal, Vec3::Z);
+        assert_eq!(facets[0].positions, [Vec3::ZERO, Vec3::X, Vec3::Y]);
+
+        match parse_ascii("facet normal 0 0 1\nvertex 0 0 0\nvertex 1 0 0\nendfacet\n", Path::new("test.stl")) {
+            Err(LoadError::Parse { line, reason, .. }) => {
+                assert_eq!(line, 4);
+                assert_eq!(reason, "facet with 2 vertices");
+            },
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("facet with 2 vertices loaded")
+        }
+    }
+
+    #[test]
+    fn welds_signed_zeros() {
+        let vertex = |position: Vec3| Vertex {
+            position,
+            normal: Vec3::Z,
+            ..Default::default()
+        };
+        let (vertices, indices) = weld(vec![
+            vertex(Vec3::new(0.0, 1.0, 0.0)),
+            vertex(Vec3::new(-0.0, 1.0, -0.0)),
+            vertex(Vec3::new(1.0, 1.0, 0.0))
+        ]);
+
+        assert_eq!(vertices.len(), 2);
+        assert_eq!(indices, [0, 0, 1]);
+    }
+}
@@ -0,0 +1,80 @@
+use glam::*;
+use std::collections::HashMap;
+use crate::Vertex;
+
+// Angle between the edges leaving `p` towards `a` and `b`, measured in the plane perpendicular to `n`.
+fn corner_angle(p: Vec3, a: Vec3, b: Vec3, n: Vec3) -> f32 {
+    let project = |v: Vec3| (v - n * n.dot(v)).normalize_or_zero();
+    project(a - p).dot(project(b - p)).clamp(-1.0, 1.0).acos()
+}
+
+// Generates per-vertex tangents the way MikkTSpace does: each triangle's texture space tangent is
+// projected into the tangent plane of the vertex normal, normalized and weighted by the corner angle.
+// Triangles with mirrored texture coordinates are accumulated separately, so vertices shared by both
+// orientations are split and `indices` is rewritten to point at the copies. The copies are appended to
+// `vertices`, and the index of the vertex each one was copied from is returned.
+pub fn generate_tangents(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    // Accumulated tangents keyed by (vertex index, whether the uv mapping preserves orientation).
+    let mut tangents: HashMap<(u32, bool), Vec3> = HashMap::new();
+    let mut orientations = Vec::with_capacity(indices.len() / 3);
+
+    for triangle in indices.chunks_exact(3) {
+        let [v0, v1, v2] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+
+        let d1 = v1.position - v0.position;
+        let d2 = v2.position - v0.position;
+        let t1 = v1.tex_coord - v0.tex_coord;
+        let t2 = v2.tex_coord - v0.tex_coord;
+
+        let signed_area = t1.perp_dot(t2);
+        let orientation = signed_area > 0.0;
+        orientations.push(orientation);
+
+        let face_tangent = (d1 * t2.y - d2 * t1.y) * signed_area.signum();
+
+        let corners = [(v0, v1, v2), (v1, v2, v0), (v2, v0, v1)];
+        for (&index, (vertex, next, prev)) in triangle.iter().zip(corners) {
+            let n = vertex.normal.normalize_or_zero();
+            let tangent = (face_tangent - n * n.dot(face_tangent)).normalize_or_zero();
+            let angle = corner_angle(vertex.position, next.position, prev.position, n);
+
+            *tangents.entry((index, orientation)).or_insert(Vec3::ZERO) += tangent * angle;
+        }
+    }
+
+    // Vertices used with both orientations keep the first one and get a copy for the other.
+    let mut remap: HashMap<(u32, bool), u32> = HashMap::new();
+    let mut copied_from = Vec::new();
+    let mut keys: Vec<_> = tangents.keys().copied().collect();
+    keys.sort();
+    let mut previous_index = None;
+    for (index, orientation) in keys {
+        let target = if previous_index == Some(index) {
+            vertices.push(vertices[index as usize]);
+            copied_from.push(index);
+            vertices.len() as u32 - 1
+        } else {
+            index
+        };
+        previous_index = Some(index);
+        remap.insert((index, orientation), target);
+
+        let vertex = &mut vertices[target as usize];
+        let tangent = tangents[&(index, orientation)];
+        let tangent = if tangent.length_squared() > 0.0 {
+            tangent.normalize()
+        } else {
+            // Degenerate texture coordinates, pick any direction perpendicular to the normal.
+            vertex.normal.try_normalize().map_or(Vec3::X, |n| n.any_orthonormal_vector())
+        };
+        vertex.tangent = Vec4::from((tangent, if orientation { 1.0 } else { -1.0 }));
+    }
+
+    for (triangle, orientation) in indices.chunks_exact_mut(3).zip(orientations) {
+        for index in triangle {
+            *index = remap[&(*index, orientation)];
+        }
+    }
+
+    copied_from
+}
@@ -1,45 +1,351 @@
 use glam::*;
-use std::ffi::CString;
+use std::ffi::CStr;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use crate::LoadError;
+use crate::ktx2::{is_ktx2, load_ktx2};
+
+#[derive(Clone, Debug)]
+enum TextureData {
+    U8(Vec<u8>),
+    F32(Vec<f32>)
+}
+
+// Order in which texels are stored. Swizzled layouts keep texels that are close in both
+// directions close in memory, so sampling along any direction stays cache friendly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureLayout {
+    Linear,
+    Tiled4x4,
+    Tiled8x8,
+    Morton
+}
+
+// How the color values of a texture are encoded. Colors are stored in sRGB, while data like normals and
+// roughness is linear.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Linear,
+    Srgb
+}
 
 #[derive(Clone, Debug)]
 pub struct Texture {
-    data: Vec<u8>,
+    data: TextureData,
     width: u32,
     height: u32,
-    channel_count: usize
+    channel_count: usize,
+    layout: TextureLayout,
+    // `None` for image files that don't state it, which are sampled as linear.
+    color_space: Option<ColorSpace>,
+    mips: Vec<Texture>
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+static SRGB_TO_LINEAR: LazyLock<[f32; 256]> = LazyLock::new(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)));
+
+fn part_1_by_1(n: usize) -> usize {
+    let mut n = n & 0xFFFF;
+    n = (n | (n << 8)) & 0x00FF00FF;
+    n = (n | (n << 4)) & 0x0F0F0F0F;
+    n = (n | (n << 2)) & 0x33333333;
+    (n | (n << 1)) & 0x55555555
+}
+
+// Number of texels in storage, including the padding swizzled layouts need.
+fn storage_size(layout: TextureLayout, width: usize, height: usize) -> usize {
+    match layout {
+        TextureLayout::Linear => width * height,
+        TextureLayout::Tiled4x4 => width.div_ceil(4) * height.div_ceil(4) * 16,
+        TextureLayout::Tiled8x8 => width.div_ceil(8) * height.div_ceil(8) * 64,
+        TextureLayout::Morton => width.next_power_of_two() * height.next_power_of_two()
+    }
+}
+
+fn texel_index(layout: TextureLayout, width: usize, height: usize, x: usize, y: usize) -> usize {
+    match layout {
+        TextureLayout::Linear => y * width + x,
+        TextureLayout::Tiled4x4 => {
+            let tiles_x = width.div_ceil(4);
+            (((y >> 2) * tiles_x + (x >> 2)) << 4) | ((y & 3) << 2) | (x & 3)
+        },
+        TextureLayout::Tiled8x8 => {
+            let tiles_x = width.div_ceil(8);
+            (((y >> 3) * tiles_x + (x >> 3)) << 6) | ((y & 7) << 3) | (x & 7)
+        },
+        TextureLayout::Morton => {
+            // Interleave the bits both dimensions share, then append the rest of the longer one.
+            let shared_bits = width.next_power_of_two().min(height.next_power_of_two()).trailing_zeros();
+            let mask = (1 << shared_bits) - 1;
+            let low = part_1_by_1(x & mask) | (part_1_by_1(y & mask) << 1);
+            low | (((x | y) >> shared_bits) << (2 * shared_bits))
+        }
+    }
+}
+
+fn reorder_values<T: Copy + Default>(
+    values: &[T],
+    channel_count: usize,
+    width: usize,
+    height: usize,
+    from: TextureLayout,
+    to: TextureLayout
+) -> Vec<T> {
+    let mut result = vec![T::default(); storage_size(to, width, height) * channel_count];
+    for y in 0..height {
+        for x in 0..width {
+            let src = texel_index(from, width, height, x, y) * channel_count;
+            let dst = texel_index(to, width, height, x, y) * channel_count;
+            result[dst..dst + channel_count].copy_from_slice(&values[src..src + channel_count]);
+        }
+    }
+    result
+}
+
+// Gray and alpha images are stored as RGBA so sampling only has to handle 1, 3 and 4 channels.
+fn expand_gray_alpha<T: Copy>(values: &[T]) -> Vec<T> {
+    values.chunks_exact(2).flat_map(|texel| [texel[0], texel[0], texel[0], texel[1]]).collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureKey {
+    File(PathBuf),
+    // An image embedded in a model file, identified by the model path and image index.
+    Embedded(PathBuf, usize)
+}
+
+// Hands out shared textures so each image source is only decoded once per color space it is used with.
+pub struct TextureCache {
+    textures: HashMap<(TextureKey, ColorSpace), Arc<Texture>>,
+    layout: TextureLayout
+}
+
+impl Default for TextureCache {
+    fn default() -> Self {
+        TextureCache {
+            textures: HashMap::new(),
+            layout: TextureLayout::Linear
+        }
+    }
+}
+
+impl TextureCache {
+    // Textures loaded through this cache are converted to `layout`.
+    pub fn with_layout(layout: TextureLayout) -> Self {
+        TextureCache {
+            layout,
+            ..Default::default()
+        }
+    }
+
+    // `color_space` is what the texture is used as, images that state their own color space keep it.
+    pub fn get_or_load<F>(&mut self, key: TextureKey, color_space: ColorSpace, load: F) -> Result<Arc<Texture>, LoadError>
+    where
+        F: FnOnce() -> Result<Texture, LoadError>
+    {
+        let key = (key, color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let texture = Arc::new(load()?.with_default_color_space(color_space).with_layout(self.layout));
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    pub fn load<P: AsRef<Path>>(&mut self, file_path: P, color_space: ColorSpace) -> Result<Arc<Texture>, LoadError> {
+        let file_path = file_path.as_ref();
+        let key = TextureKey::File(file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf()));
+        self.get_or_load(key, color_space, || load_texture(file_path))
+    }
+}
+
+pub fn load_texture<P: AsRef<Path>>(file_path: P) -> Result<Texture, LoadError> {
+    let file_path = file_path.as_ref();
+    let bytes = std::fs::read(file_path).map_err(|source| LoadError::Io {
+        path: file_path.to_path_buf(),
+        source
+    })?;
+
+    load_texture_from_memory(&bytes, file_path)
 }
 
-pub fn load_texture(file_path: &str) -> Texture {
-    let file_path = CString::new(file_path.as_bytes()).unwrap();
+// Decodes an encoded image; `file_path` names the source in errors.
+pub fn load_texture_from_memory(bytes: &[u8], file_path: &Path) -> Result<Texture, LoadError> {
+    if is_ktx2(bytes) {
+        return load_ktx2(bytes, file_path);
+    }
 
     unsafe {
+        let is_hdr = stb_image::stb_image::bindgen::stbi_is_hdr_from_memory(
+            bytes.as_ptr(),
+            bytes.len() as i32
+        ) != 0;
+
         let mut width = 0;
         let mut height = 0;
         let mut channel_count = 0;
-        let data = stb_image::stb_image::bindgen::stbi_load(
-            file_path.as_ptr(),
-            &mut width,
-            &mut height,
-            &mut channel_count,
-            0,
-        );
-
-        assert!(!data.is_null(), "Failed to load texture.");
-        let data: Vec<u8> = std::slice::from_raw_parts(
-            data,
-            (width * height * channel_count) as usize
-        ).to_vec();
+        let data = if is_hdr {
+            stb_image::stb_image::bindgen::stbi_loadf_from_memory(
+                bytes.as_ptr(),
+                bytes.len() as i32,
+                &mut width,
+                &mut height,
+                &mut channel_count,
+                0,
+            ) as *mut u8
+        } else {
+            stb_image::stb_image::bindgen::stbi_load_from_memory(
+                bytes.as_ptr(),
+                bytes.len() as i32,
+                &mut width,
+                &mut height,
+                &mut channel_count,
+                0,
+            )
+        };
 
-        Texture {
-            data,
+        if data.is_null() {
+            let reason = CStr::from_ptr(stb_image::stb_image::bindgen::stbi_failure_reason())
+                .to_string_lossy()
+                .into_owned();
+
+            return Err(LoadError::ImageDecode {
+                path: file_path.to_path_buf(),
+                reason
+            });
+        }
+
+        let value_count = (width * height * channel_count) as usize;
+        let pixels = if is_hdr {
+            TextureData::F32(std::slice::from_raw_parts(data as *const f32, value_count).to_vec())
+        } else {
+            TextureData::U8(std::slice::from_raw_parts(data, value_count).to_vec())
+        };
+        stb_image::stb_image::bindgen::stbi_image_free(data as *mut _);
+
+        let (pixels, channel_count) = match (pixels, channel_count) {
+            (TextureData::U8(values), 2) => (TextureData::U8(expand_gray_alpha(&values)), 4),
+            (TextureData::F32(values), 2) => (TextureData::F32(expand_gray_alpha(&values)), 4),
+            (pixels, 1 | 3 | 4) => (pixels, channel_count as usize),
+            _ => {
+                return Err(LoadError::Unsupported {
+                    path: file_path.to_path_buf(),
+                    feature: format!("textures with {} channels", channel_count)
+                });
+            }
+        };
+
+        Ok(Texture {
+            data: pixels,
             width: width as u32,
             height: height as u32,
-            channel_count: channel_count as usize
-        }
+            channel_count,
+            layout: TextureLayout::Linear,
+            color_space: None,
+            mips: Vec::new()
+        })
     }
 }
 
 impl Texture {
+    pub fn new(data: Vec<u8>, width: u32, height: u32, channel_count: usize) -> Self {
+        Texture {
+            data: TextureData::U8(data),
+            width,
+            height,
+            channel_count,
+            layout: TextureLayout::Linear,
+            color_space: None,
+            mips: Vec::new()
+        }
+    }
+
+    pub fn new_f32(data: Vec<f32>, width: u32, height: u32, channel_count: usize) -> Self {
+        Texture {
+            data: TextureData::F32(data),
+            width,
+            height,
+            channel_count,
+            layout: TextureLayout::Linear,
+            color_space: None,
+            mips: Vec::new()
+        }
+    }
+
+    pub fn with_mips(mut self, mips: Vec<Texture>) -> Self {
+        self.mips = mips;
+        self
+    }
+
+    // Sets the color space of the texture and its mips, unless its file stated one.
+    pub fn with_default_color_space(mut self, color_space: ColorSpace) -> Self {
+        if self.color_space.is_none() {
+            self.color_space = Some(color_space);
+            self.mips = self.mips.into_iter().map(|mip| mip.with_default_color_space(color_space)).collect();
+        }
+        self
+    }
+
+    // Converts the texture and its mips into the given storage layout.
+    pub fn with_layout(self, layout: TextureLayout) -> Self {
+        if self.layout == layout {
+            return self;
+        }
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let data = match &self.data {
+            TextureData::U8(values) => TextureData::U8(
+                reorder_values(values, self.channel_count, width, height, self.layout, layout)
+            ),
+            TextureData::F32(values) => TextureData::F32(
+                reorder_values(values, self.channel_count, width, height, self.layout, layout)
+            )
+        };
+
+        Texture {
+            data,
+            layout,
+            mips: self.mips.into_iter().map(|mip| mip.with_layout(layout)).collect(),
+            ..self
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Level of detail for a triangle covering `uv_area` in texture space and `screen_area` pixels.
+    pub fn lod(&self, uv_area: f32, screen_area: f32) -> f32 {
+        let texel_area = uv_area * self.width as f32 * self.height as f32;
+        if texel_area <= 0.0 || screen_area <= 0.0 {
+            return 0.0;
+        }
+        (0.5 * (texel_area / screen_area).log2()).max(0.0)
+    }
+
+    pub fn sample_pixel_lod(&self, x: f32, y: f32, lod: f32) -> Vec4 {
+        if self.mips.is_empty() || lod < 0.5 {
+            return self.sample_pixel(x, y);
+        }
+
+        let level = (lod.round() as usize).min(self.mips.len());
+        self.mips[level - 1].sample_pixel(x, y)
+    }
+
     pub fn sample_pixel(&self, x: f32, y: f32) -> Vec4 {
         let inv_dims = Vec2::new(1.0 / self.width as f32, 1.0 / self.height as f32);
 
@@ -59,23 +365,28 @@ impl Texture {
     }
 
     pub fn get_pixel(&self, x: f32, y: f32) -> Vec4 {
-        let x = ((x * self.width as f32) as usize) % (self.width - 1) as usize;
-        let y = ((y * self.height as f32) as usize) % (self.height - 1) as usize;
+        let x = ((x * self.width as f32) as usize) % self.width as usize;
+        let y = ((y * self.height as f32) as usize) % self.height as usize;
+
+        let index = texel_index(self.layout, self.width as usize, self.height as usize, x, y) * self.channel_count;
+        let srgb = self.color_space == Some(ColorSpace::Srgb);
+        let linear = |channel: usize| match &self.data {
+            TextureData::U8(data) => data[index + channel] as f32 / 255.99,
+            TextureData::F32(data) => data[index + channel]
+        };
+        // 8 bit sRGB colors are made linear before filtering, like GPUs do. Alpha is always linear.
+        let color = |channel: usize| match &self.data {
+            TextureData::U8(data) if srgb => SRGB_TO_LINEAR[data[index + channel] as usize],
+            _ => linear(channel)
+        };
 
         match self.channel_count {
-            4 => {
-                let data: &Vec<(u8, u8, u8, u8)> = unsafe { std::mem::transmute(&self.data) };
-                let pixel = &data[y * (self.width as usize) + x];
-    
-                Vec4::new(pixel.0 as f32 / 255.99, pixel.1 as f32 / 255.99, pixel.2 as f32 / 255.99, pixel.3 as f32 / 255.99)
-            },
-            3 => {
-                let data: &Vec<(u8, u8, u8)> = unsafe { std::mem::transmute(&self.data) };
-                let pixel = &data[y * (self.width as usize) + x];
-    
-                Vec4::new(pixel.0 as f32 / 255.99, pixel.1 as f32 / 255.99, pixel.2 as f32 / 255.99, 0.0)
-            }
-            _ => panic!("Failed to get pixel. (Unsupported channel count)")
+            0 => Vec4::ZERO,
+            // Single channel textures are returned as gray.
+            1 => Vec4::from((Vec3::splat(color(0)), 0.0)),
+            2 => Vec4::from((Vec3::splat(color(0)), linear(1))),
+            3 => Vec4::new(color(0), color(1), color(2), 0.0),
+            _ => Vec4::new(color(0), color(1), color(2), linear(3))
         }
     }
-}
\ No newline at end of file
+}
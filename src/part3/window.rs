@@ -35,6 +35,11 @@ impl Window {
         !self.window.is_open()
     }
 
+    // Whether `key` went down since the last frame.
+    pub fn is_key_pressed(&self, key: minifb::Key) -> bool {
+        self.window.is_key_pressed(key, minifb::KeyRepeat::No)
+    }
+
     pub fn display(&mut self) {
         self.window.update_with_buffer(
             &self.framebuffer.data,
@@ -78,7 +83,7 @@ impl Framebuffer {
         self.data[y * self.width + x] = (value * u32::MAX as f32) as u32;
     }
 
-    pub fn get_pixel_f32(&mut self, x: usize, y: usize) -> f32 {
+    pub fn get_pixel_f32(&self, x: usize, y: usize) -> f32 {
         self.data[y * self.width + x] as f32 / u32::MAX as f32
     }
 